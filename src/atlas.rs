@@ -0,0 +1,303 @@
+use bevy::{
+    asset::{AssetLoader, AsyncReadExt, LoadedFolder},
+    ecs::system::SystemParam,
+    prelude::*,
+    utils::HashMap,
+};
+
+use crate::map::TileSprite;
+
+pub fn plugin(app: &mut App) {
+    app.init_asset::<AtlasDefinition>()
+        .register_asset_loader(AtlasDefinitionLoader)
+        .init_resource::<SpriteSheets>()
+        .add_systems(PostUpdate, (set_sprite, animate_sprites).chain());
+}
+
+/// Every `.atlas` file in `assets/atlases`, when two sheets name the same cell
+/// the one with the highest priority is used
+#[derive(Resource)]
+pub struct SpriteSheets(pub Handle<LoadedFolder>);
+
+impl FromWorld for SpriteSheets {
+    fn from_world(world: &mut World) -> Self {
+        SpriteSheets(world.resource::<AssetServer>().load_folder("atlases"))
+    }
+}
+
+impl SpriteSheets {
+    /// Find the sheet with the highest priority that has a cell for `sprite`
+    pub fn find<'a>(
+        &self,
+        sprite: TileSprite,
+        folders: &Assets<LoadedFolder>,
+        atlases: &'a Assets<AtlasDefinition>,
+    ) -> Option<(&'a AtlasDefinition, &'a CellDefinition)> {
+        let folder = folders.get(self.0.id())?;
+        folder
+            .handles
+            .iter()
+            .filter_map(|handle| handle.id().try_typed::<AtlasDefinition>().ok())
+            .filter_map(|id| atlases.get(id))
+            .filter_map(|atlas| Some((atlas, atlas.cell(sprite)?)))
+            .max_by_key(|(atlas, _)| atlas.priority)
+    }
+}
+
+/// Looks up cells across every sheet, for systems that care what a sprite is rather
+/// than how it looks
+#[derive(SystemParam)]
+pub struct Cells<'w> {
+    sheets: Res<'w, SpriteSheets>,
+    folders: Res<'w, Assets<LoadedFolder>>,
+    atlases: Res<'w, Assets<AtlasDefinition>>,
+}
+
+impl Cells<'_> {
+    pub fn get(&self, sprite: TileSprite) -> Option<&CellDefinition> {
+        self.sheets
+            .find(sprite, &self.folders, &self.atlases)
+            .map(|(_, cell)| cell)
+    }
+
+    /// Sprites that are in no sheet, or whose sheet has not loaded, are not solid
+    pub fn is_solid(&self, sprite: TileSprite) -> bool {
+        self.get(sprite).is_some_and(|cell| cell.solid)
+    }
+}
+
+#[derive(Asset, TypePath, Debug)]
+pub struct AtlasDefinition {
+    pub image: Handle<Image>,
+    pub layout: Handle<TextureAtlasLayout>,
+    pub priority: i8,
+    cells: Vec<CellDefinition>,
+    names: HashMap<String, usize>,
+}
+
+impl AtlasDefinition {
+    pub fn cell(&self, sprite: TileSprite) -> Option<&CellDefinition> {
+        let name: &'static str = sprite.into();
+        self.names.get(name).map(|&i| &self.cells[i])
+    }
+
+    /// A sheet with no image, for tests that only care about what the cells say
+    #[cfg(test)]
+    pub fn from_cells(cells: Vec<CellDefinition>) -> AtlasDefinition {
+        let names = cells
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| (cell.name.clone(), i))
+            .collect();
+        AtlasDefinition {
+            image: Handle::default(),
+            layout: Handle::default(),
+            priority: 0,
+            cells,
+            names,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
+pub struct CellDefinition {
+    pub name: String,
+    pub index: usize,
+    /// tiles showing this cell get a collider, and count as solid when their
+    /// neighbours autotile
+    #[serde(default)]
+    pub solid: bool,
+    /// free form, shown in the editor to help find cells
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub animation: Option<CellAnimation>,
+}
+
+/// Loops through the listed cells, by name, `fps` times a second
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
+pub struct CellAnimation {
+    pub frames: Vec<String>,
+    pub fps: f32,
+}
+
+/// The on disk format of an `.atlas` file
+#[derive(serde::Deserialize, serde::Serialize)]
+struct AtlasFile {
+    image: String,
+    tile_size: UVec2,
+    columns: u32,
+    rows: u32,
+    padding: Option<UVec2>,
+    offset: Option<UVec2>,
+    #[serde(default)]
+    priority: i8,
+    cells: Vec<CellDefinition>,
+}
+
+struct AtlasDefinitionLoader;
+
+impl AssetLoader for AtlasDefinitionLoader {
+    type Asset = AtlasDefinition;
+    type Settings = ();
+    type Error = &'static str;
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut bevy::asset::io::Reader,
+        _settings: &'a Self::Settings,
+        load_context: &'a mut bevy::asset::LoadContext,
+    ) -> impl bevy::utils::ConditionalSendFuture<Output = Result<Self::Asset, Self::Error>> {
+        load_atlas_definition(reader, load_context)
+    }
+    fn extensions(&self) -> &[&str] {
+        &["atlas"]
+    }
+}
+
+async fn load_atlas_definition(
+    reader: &mut bevy::asset::io::Reader<'_>,
+    load_context: &mut bevy::asset::LoadContext<'_>,
+) -> Result<AtlasDefinition, &'static str> {
+    let mut data = String::new();
+    if reader.read_to_string(&mut data).await.is_err() {
+        return Err("Failed to read to string");
+    }
+    let file: AtlasFile = match ron::from_str(&data) {
+        Ok(data) => data,
+        Err(e) => {
+            error!("{e}");
+            return Err("Ron Failed");
+        }
+    };
+
+    let mut names = HashMap::default();
+    for (i, cell) in file.cells.iter().enumerate() {
+        if cell.index >= (file.columns * file.rows) as usize {
+            error!("{} is outside the atlas", cell.name);
+            return Err("Cell index out of bounds");
+        }
+        if names.insert(cell.name.clone(), i).is_some() {
+            error!("{} is defined twice", cell.name);
+            return Err("Duplicate cell name");
+        }
+    }
+    for cell in file.cells.iter() {
+        let Some(animation) = &cell.animation else {
+            continue;
+        };
+        if animation
+            .frames
+            .iter()
+            .any(|frame| !names.contains_key(frame))
+        {
+            error!(
+                "{} has an animation frame that is not in the atlas",
+                cell.name
+            );
+            return Err("Unknown animation frame");
+        }
+    }
+
+    let layout = TextureAtlasLayout::from_grid(
+        file.tile_size,
+        file.columns,
+        file.rows,
+        file.padding,
+        file.offset,
+    );
+    Ok(AtlasDefinition {
+        image: load_context.load(file.image),
+        layout: load_context.add_labeled_asset("layout".to_string(), layout),
+        priority: file.priority,
+        cells: file.cells,
+        names,
+    })
+}
+
+#[derive(Component)]
+struct SpriteAnimation {
+    frames: Vec<usize>,
+    timer: Timer,
+    frame: usize,
+}
+
+type SheetSprite = (
+    Entity,
+    Ref<'static, TileSprite>,
+    &'static mut Handle<Image>,
+    &'static mut TextureAtlas,
+    &'static mut Visibility,
+);
+
+/// Point every changed `TileSprite` at its cell, and every sprite again
+/// whenever an atlas is (re)loaded so edits to the `.atlas` files hot reload.
+/// Sprites stay hidden until they have been found in a sheet
+fn set_sprite(
+    mut commands: Commands,
+    mut tiles: Query<SheetSprite>,
+    mut events: EventReader<AssetEvent<AtlasDefinition>>,
+    mut folder_events: EventReader<AssetEvent<LoadedFolder>>,
+    sheets: Res<SpriteSheets>,
+    folders: Res<Assets<LoadedFolder>>,
+    atlases: Res<Assets<AtlasDefinition>>,
+) {
+    let reload = events.read().count() + folder_events.read().count() > 0;
+    for (entity, tile, mut image, mut atlas, mut visibility) in &mut tiles {
+        if !reload && !tile.is_changed() {
+            continue;
+        }
+        let Some((sheet, cell)) = sheets.find(*tile, &folders, &atlases) else {
+            continue;
+        };
+        *image = sheet.image.clone();
+        atlas.layout = sheet.layout.clone();
+        atlas.index = cell.index;
+        *visibility = Visibility::Inherited;
+        if let Some(animation) = &cell.animation {
+            commands.entity(entity).insert(SpriteAnimation {
+                frames: animation
+                    .frames
+                    .iter()
+                    .filter_map(|frame| sheet.names.get(frame))
+                    .map(|&i| sheet.cells[i].index)
+                    .collect(),
+                timer: Timer::from_seconds(1. / animation.fps, TimerMode::Repeating),
+                frame: 0,
+            });
+        } else {
+            commands.entity(entity).remove::<SpriteAnimation>();
+        }
+    }
+}
+
+fn animate_sprites(mut sprites: Query<(&mut SpriteAnimation, &mut TextureAtlas)>, time: Res<Time>) {
+    for (mut animation, mut atlas) in &mut sprites {
+        animation.timer.tick(time.delta());
+        if animation.frames.is_empty() {
+            continue;
+        }
+        for _ in 0..animation.timer.times_finished_this_tick() {
+            animation.frame = (animation.frame + 1) % animation.frames.len();
+        }
+        atlas.index = animation.frames[animation.frame];
+    }
+}
+
+#[test]
+fn atlas_files_name_every_sprite() {
+    use strum::IntoEnumIterator;
+    for path in [
+        "assets/atlases/tilemap.atlas",
+        "assets/atlases/tilemap_padded.atlas",
+    ] {
+        let data = std::fs::read_to_string(path).unwrap();
+        let file: AtlasFile = ron::from_str(&data).unwrap();
+        for sprite in TileSprite::iter() {
+            let name: &'static str = sprite.into();
+            assert!(
+                file.cells.iter().any(|cell| cell.name == name),
+                "{name} missing from {path}"
+            );
+        }
+    }
+}
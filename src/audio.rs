@@ -0,0 +1,341 @@
+//! Sound effects for things that happen in game and a looping track per map. Which clips
+//! play is read from `assets/audio/game.sounds`, so sounds can be swapped or retuned
+//! without recompiling
+
+use bevy::{
+    asset::{AssetLoader, AsyncReadExt},
+    audio::Volume,
+    prelude::*,
+    utils::HashMap,
+};
+use rand::{seq::SliceRandom, Rng};
+
+use crate::map::{CurrentMap, MapData};
+
+pub fn plugin(app: &mut App) {
+    app.init_asset::<SoundBank>()
+        .register_asset_loader(SoundBankLoader)
+        .add_event::<Sound>()
+        .init_resource::<Sounds>()
+        .init_resource::<AudioMix>()
+        .add_systems(
+            Update,
+            (load_clips, play_sounds, play_music, fade_music).chain(),
+        );
+}
+
+pub const SOUNDS_PATH: &str = "audio/game.sounds";
+
+/// Send to play the sound for something that happened in game
+#[derive(Event, serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Sound {
+    Jump,
+    Shoot,
+    Stomp,
+    Coin,
+    /// a player lost a point of health
+    Damage,
+}
+
+/// Everything in a `.sounds` file
+#[derive(serde::Deserialize, serde::Serialize, Asset, TypePath, Clone, Debug)]
+#[serde(default)]
+pub struct SoundBank {
+    pub effects: HashMap<Sound, Effect>,
+    /// track files by the name maps use for them
+    pub music: HashMap<String, String>,
+    /// in seconds, how long one track takes to fade into the next
+    pub crossfade: f32,
+}
+
+impl Default for SoundBank {
+    fn default() -> Self {
+        SoundBank {
+            effects: HashMap::default(),
+            music: HashMap::default(),
+            crossfade: 1.5,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct Effect {
+    /// one of these is picked each time the sound plays
+    pub clips: Vec<String>,
+    pub volume: f32,
+    /// how far the pitch is moved at random either way, 0.1 is up to 10% higher or lower
+    pub pitch: f32,
+    /// how many of this sound can play at once, any more are dropped
+    pub voices: usize,
+}
+
+impl Default for Effect {
+    fn default() -> Self {
+        Effect {
+            clips: Vec::new(),
+            volume: 1.,
+            pitch: 0.05,
+            voices: 4,
+        }
+    }
+}
+
+/// The sound file and the clips it names, which are loaded whenever it changes
+#[derive(Resource)]
+pub struct Sounds {
+    pub handle: Handle<SoundBank>,
+    pub bank: SoundBank,
+    pub effects: HashMap<Sound, Vec<Handle<AudioSource>>>,
+    pub music: HashMap<String, Handle<AudioSource>>,
+}
+
+impl FromWorld for Sounds {
+    fn from_world(world: &mut World) -> Self {
+        Sounds {
+            handle: world.resource::<AssetServer>().load(SOUNDS_PATH),
+            bank: SoundBank::default(),
+            effects: HashMap::default(),
+            music: HashMap::default(),
+        }
+    }
+}
+
+/// How loud everything is, from the player's settings
+#[derive(Resource)]
+pub struct AudioMix {
+    pub master: f32,
+    pub music: f32,
+    pub effects: f32,
+}
+
+impl Default for AudioMix {
+    fn default() -> Self {
+        AudioMix {
+            master: 1.,
+            music: 1.,
+            effects: 1.,
+        }
+    }
+}
+
+/// A sound effect that is playing
+#[derive(Component)]
+pub struct Voice(pub Sound);
+
+/// A track that is playing, fading in until it is at full volume or fading out until
+/// it is gone
+#[derive(Component)]
+struct Music {
+    track: String,
+    /// from 0 to 1
+    level: f32,
+    fading_out: bool,
+}
+
+fn load_clips(
+    mut events: EventReader<AssetEvent<SoundBank>>,
+    mut sounds: ResMut<Sounds>,
+    banks: Res<Assets<SoundBank>>,
+    asset_server: Res<AssetServer>,
+) {
+    let id = sounds.handle.id();
+    if !events
+        .read()
+        .any(|event| event.is_loaded_with_dependencies(id) || event.is_modified(id))
+    {
+        return;
+    }
+    let Some(bank) = banks.get(id) else {
+        return;
+    };
+    sounds.effects = bank
+        .effects
+        .iter()
+        .map(|(sound, effect)| {
+            let clips = effect
+                .clips
+                .iter()
+                .map(|path| asset_server.load(path))
+                .collect();
+            (*sound, clips)
+        })
+        .collect();
+    sounds.music = bank
+        .music
+        .iter()
+        .map(|(track, path)| (track.clone(), asset_server.load(path)))
+        .collect();
+    sounds.bank = bank.clone();
+}
+
+/// Clips that are missing or still loading are skipped rather than played late
+fn play_sounds(
+    mut commands: Commands,
+    mut events: EventReader<Sound>,
+    sounds: Res<Sounds>,
+    clips: Res<Assets<AudioSource>>,
+    voices: Query<&Voice>,
+    mix: Res<AudioMix>,
+) {
+    let mut playing = HashMap::<Sound, usize>::default();
+    for Voice(sound) in &voices {
+        *playing.entry(*sound).or_default() += 1;
+    }
+    let mut rng = rand::thread_rng();
+    for sound in events.read() {
+        let Some(effect) = sounds.bank.effects.get(sound) else {
+            continue;
+        };
+        let count = playing.entry(*sound).or_default();
+        if *count >= effect.voices {
+            continue;
+        }
+        let Some(clip) = sounds
+            .effects
+            .get(sound)
+            .and_then(|clips| clips.choose(&mut rng))
+        else {
+            continue;
+        };
+        if !clips.contains(clip) {
+            continue;
+        }
+        *count += 1;
+        let pitch = effect.pitch.abs();
+        commands.spawn((
+            Name::new(format!("{sound:?} Sound")),
+            Voice(*sound),
+            AudioBundle {
+                source: clip.clone(),
+                settings: PlaybackSettings::DESPAWN
+                    .with_speed(1. + rng.gen_range(-pitch..=pitch))
+                    .with_volume(Volume::new(mix.master * mix.effects * effect.volume)),
+            },
+        ));
+    }
+}
+
+/// Fade in whatever track the map asks for once it has loaded, fading out the last one
+fn play_music(
+    mut commands: Commands,
+    mut tracks: Query<&mut Music>,
+    sounds: Res<Sounds>,
+    clips: Res<Assets<AudioSource>>,
+    maps: Res<Assets<MapData>>,
+    current: Res<CurrentMap>,
+) {
+    let Some(map) = maps.get(&current.0) else {
+        return;
+    };
+    let wanted = map.music.as_deref();
+    let mut playing = false;
+    for mut music in &mut tracks {
+        if !music.fading_out && Some(music.track.as_str()) == wanted {
+            playing = true;
+        } else {
+            music.fading_out = true;
+        }
+    }
+    if playing {
+        return;
+    }
+    let Some((track, clip)) = wanted.and_then(|track| Some((track, sounds.music.get(track)?)))
+    else {
+        return;
+    };
+    if !clips.contains(clip) {
+        return;
+    }
+    commands.spawn((
+        Name::new(format!("{track} Music")),
+        Music {
+            track: track.to_string(),
+            level: 0.,
+            fading_out: false,
+        },
+        AudioBundle {
+            source: clip.clone(),
+            settings: PlaybackSettings::LOOP.with_volume(Volume::new(0.)),
+        },
+    ));
+}
+
+fn fade_music(
+    mut commands: Commands,
+    mut tracks: Query<(Entity, &mut Music, Option<&AudioSink>)>,
+    sounds: Res<Sounds>,
+    mix: Res<AudioMix>,
+    time: Res<Time<Real>>,
+) {
+    let step = time.delta_seconds() / sounds.bank.crossfade.max(0.01);
+    for (entity, mut music, sink) in &mut tracks {
+        music.level = if music.fading_out {
+            music.level - step
+        } else {
+            music.level + step
+        }
+        .clamp(0., 1.);
+        if music.fading_out && music.level == 0. {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        if let Some(sink) = sink {
+            sink.set_volume(mix.master * mix.music * music.level);
+        }
+    }
+}
+
+struct SoundBankLoader;
+
+impl AssetLoader for SoundBankLoader {
+    type Asset = SoundBank;
+    type Settings = ();
+    type Error = &'static str;
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut bevy::asset::io::Reader,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut bevy::asset::LoadContext,
+    ) -> impl bevy::utils::ConditionalSendFuture<Output = Result<Self::Asset, Self::Error>> {
+        load_sound_bank(reader)
+    }
+    fn extensions(&self) -> &[&str] {
+        &["sounds"]
+    }
+}
+
+async fn load_sound_bank(
+    reader: &mut bevy::asset::io::Reader<'_>,
+) -> Result<SoundBank, &'static str> {
+    let mut data = String::new();
+    if reader.read_to_string(&mut data).await.is_err() {
+        return Err("Failed to read to string");
+    }
+    match ron::from_str(&data) {
+        Ok(data) => Ok(data),
+        Err(e) => {
+            error!("{e}");
+            Err("Ron Failed")
+        }
+    }
+}
+
+#[test]
+fn every_clip_decodes() {
+    use bevy::audio::Decodable;
+    let bank: SoundBank =
+        ron::from_str(&std::fs::read_to_string(format!("assets/{SOUNDS_PATH}")).unwrap()).unwrap();
+    let clips = bank.effects.values().flat_map(|effect| &effect.clips);
+    for path in clips.chain(bank.music.values()) {
+        let bytes =
+            std::fs::read(format!("assets/{path}")).unwrap_or_else(|e| panic!("{path}: {e}"));
+        let source = AudioSource {
+            bytes: bytes.into(),
+        };
+        assert!(
+            source.decoder().any(|sample| sample != 0),
+            "{path} is silent"
+        );
+    }
+}
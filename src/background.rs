@@ -0,0 +1,286 @@
+//! Layers of sprites behind the map that scroll slower than the camera. A map picks one
+//! with `Background <name>`, which loads `assets/backgrounds/<name>.background`
+
+use bevy::{
+    asset::{AssetLoader, AsyncReadExt},
+    prelude::*,
+};
+
+use crate::{
+    camera::{camera_effects, MainCamera, CANVAS_HEIGHT, CANVAS_WIDTH, PIXEL_SIZE},
+    map::{CurrentMap, MapBounds, MapData, TileSprite},
+    progression::Section,
+};
+
+pub fn plugin(app: &mut App) {
+    app.init_asset::<Background>()
+        .register_asset_loader(BackgroundLoader)
+        .init_resource::<CurrentBackground>()
+        .add_systems(Update, (pick_background, spawn_layers).chain())
+        .add_systems(
+            PostUpdate,
+            (scroll_layers, tint_layers)
+                .after(camera_effects)
+                .before(TransformSystem::TransformPropagate),
+        );
+}
+
+/// Behind every tile, layers further down the list are drawn in front
+const BACKGROUND_Z: f32 = -100.;
+
+#[derive(serde::Deserialize, serde::Serialize, Asset, TypePath, Clone, Debug)]
+pub struct Background {
+    /// the colour behind every layer, in srgb
+    pub sky: (f32, f32, f32),
+    /// drawn back to front
+    pub layers: Vec<BackgroundLayer>,
+    #[serde(default)]
+    pub depth_tint: Option<DepthTint>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
+pub struct BackgroundLayer {
+    pub sprite: TileSprite,
+    /// how big each sprite is drawn, in world units
+    pub size: f32,
+    /// how far apart the sprites are, the same as `size` unless given
+    #[serde(default)]
+    pub spacing: Option<f32>,
+    /// how far the layer moves compared to the camera, 0 stays still on screen and 1
+    /// moves with the map
+    pub scroll: f32,
+    /// in srgb
+    pub color: (f32, f32, f32),
+}
+
+/// Fades everything towards `color` the deeper the camera is
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
+pub struct DepthTint {
+    pub color: (f32, f32, f32),
+    /// how many tiles below the top of a map the tint is complete
+    pub depth: f32,
+    /// how far into the tint each section after the first starts, from 0 to 1
+    pub per_section: f32,
+}
+
+impl DepthTint {
+    /// From 0 at the top of the first section to 1 once fully tinted
+    fn amount(&self, depth: f32, section: u32) -> f32 {
+        let sections = section.saturating_sub(1) as f32 * self.per_section;
+        (depth / 32. / self.depth.max(1.) + sections).clamp(0., 1.)
+    }
+
+    fn apply(&self, color: Vec3, amount: f32) -> Color {
+        let tinted = color.lerp(Vec3::from(self.color), amount);
+        Color::srgb(tinted.x, tinted.y, tinted.z)
+    }
+}
+
+/// The background named by the current map, if it names one
+#[derive(Resource, Default)]
+pub struct CurrentBackground {
+    pub name: Option<String>,
+    pub handle: Handle<Background>,
+}
+
+#[derive(Component)]
+struct ParallaxLayer {
+    scroll: f32,
+    /// the pattern repeats this often
+    spacing: f32,
+    color: Vec3,
+}
+
+fn pick_background(
+    mut current: ResMut<CurrentBackground>,
+    maps: Res<Assets<MapData>>,
+    map: Res<CurrentMap>,
+    asset_server: Res<AssetServer>,
+) {
+    let Some(map) = maps.get(&map.0) else {
+        return;
+    };
+    if map.background == current.name {
+        return;
+    }
+    current.handle = match &map.background {
+        Some(name) => asset_server.load(format!("backgrounds/{name}.background")),
+        None => Handle::default(),
+    };
+    current.name = map.background.clone();
+}
+
+/// Build the layers again whenever the map picks a different background or its file
+/// changes
+fn spawn_layers(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<Background>>,
+    current: Res<CurrentBackground>,
+    backgrounds: Res<Assets<Background>>,
+    layers: Query<Entity, With<ParallaxLayer>>,
+    mut cameras: Query<&mut Camera, With<MainCamera>>,
+) {
+    let id = current.handle.id();
+    let reload = events
+        .read()
+        .filter(|event| event.is_loaded_with_dependencies(id) || event.is_modified(id))
+        .count()
+        > 0;
+    if !reload && !current.is_changed() {
+        return;
+    }
+    for layer in &layers {
+        commands.entity(layer).despawn_recursive();
+    }
+    let background = backgrounds.get(id);
+    for mut camera in &mut cameras {
+        camera.clear_color = match background {
+            Some(background) => {
+                let (r, g, b) = background.sky;
+                ClearColorConfig::Custom(Color::srgb(r, g, b))
+            }
+            None => ClearColorConfig::Default,
+        };
+    }
+    let Some(background) = background else {
+        return;
+    };
+    let view = Vec2::new(CANVAS_WIDTH as f32, CANVAS_HEIGHT as f32) * PIXEL_SIZE;
+    for (i, layer) in background.layers.iter().enumerate() {
+        let spacing = layer.spacing.unwrap_or(layer.size).max(1.);
+        // one spare on each side so the edges never show as the layer wraps around
+        let cells = (view / spacing).ceil().as_ivec2() + 2;
+        let color = Vec3::from(layer.color);
+        commands
+            .spawn((
+                Name::new(format!("Background Layer {i}")),
+                ParallaxLayer {
+                    scroll: layer.scroll,
+                    spacing,
+                    color,
+                },
+                SpatialBundle::from_transform(Transform::from_xyz(0., 0., BACKGROUND_Z + i as f32)),
+            ))
+            .with_children(|parent| {
+                for x in 0..cells.x {
+                    for y in 0..cells.y {
+                        let offset =
+                            (IVec2::new(x, y).as_vec2() - (cells - 1).as_vec2() / 2.) * spacing;
+                        parent.spawn((
+                            SpriteBundle {
+                                transform: Transform::from_translation(offset.extend(0.)),
+                                sprite: Sprite {
+                                    custom_size: Some(Vec2::splat(layer.size)),
+                                    color: Color::srgb(color.x, color.y, color.z),
+                                    ..default()
+                                },
+                                visibility: Visibility::Hidden,
+                                ..default()
+                            },
+                            TextureAtlas::default(),
+                            layer.sprite,
+                        ));
+                    }
+                }
+            });
+    }
+}
+
+/// Keep each layer around the camera, shifted so its pattern moves `scroll` times as
+/// far as the camera does
+fn scroll_layers(
+    cameras: Query<&Transform, With<MainCamera>>,
+    mut layers: Query<(&ParallaxLayer, &mut Transform), Without<MainCamera>>,
+) {
+    let Ok(camera) = cameras.get_single() else {
+        return;
+    };
+    let camera = camera.translation.truncate();
+    for (layer, mut transform) in &mut layers {
+        let shift = (camera * layer.scroll).rem_euclid(Vec2::splat(layer.spacing));
+        let position = camera - shift;
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+    }
+}
+
+fn tint_layers(
+    mut cameras: Query<(&Transform, &mut Camera), With<MainCamera>>,
+    layers: Query<(&ParallaxLayer, &Children)>,
+    mut sprites: Query<&mut Sprite>,
+    bounds: Query<&MapBounds>,
+    section: Res<Section>,
+    current: Res<CurrentBackground>,
+    backgrounds: Res<Assets<Background>>,
+) {
+    let Some(background) = backgrounds.get(&current.handle) else {
+        return;
+    };
+    let Some(tint) = &background.depth_tint else {
+        return;
+    };
+    let (Ok((camera, mut view)), Ok(bounds)) = (cameras.get_single_mut(), bounds.get_single())
+    else {
+        return;
+    };
+    let amount = tint.amount(bounds.0.max.y - camera.translation.y, section.number);
+    view.clear_color = ClearColorConfig::Custom(tint.apply(Vec3::from(background.sky), amount));
+    for (layer, children) in &layers {
+        let color = tint.apply(layer.color, amount);
+        let mut sprites = sprites.iter_many_mut(children);
+        while let Some(mut sprite) = sprites.fetch_next() {
+            if sprite.color != color {
+                sprite.color = color;
+            }
+        }
+    }
+}
+
+struct BackgroundLoader;
+
+impl AssetLoader for BackgroundLoader {
+    type Asset = Background;
+    type Settings = ();
+    type Error = &'static str;
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut bevy::asset::io::Reader,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut bevy::asset::LoadContext,
+    ) -> impl bevy::utils::ConditionalSendFuture<Output = Result<Self::Asset, Self::Error>> {
+        load_background(reader)
+    }
+    fn extensions(&self) -> &[&str] {
+        &["background"]
+    }
+}
+
+async fn load_background(
+    reader: &mut bevy::asset::io::Reader<'_>,
+) -> Result<Background, &'static str> {
+    let mut data = String::new();
+    if reader.read_to_string(&mut data).await.is_err() {
+        return Err("Failed to read to string");
+    }
+    match ron::from_str(&data) {
+        Ok(data) => Ok(data),
+        Err(e) => {
+            error!("{e}");
+            Err("Ron Failed")
+        }
+    }
+}
+
+#[test]
+fn deeper_sections_start_darker() {
+    let tint = DepthTint {
+        color: (0., 0., 0.),
+        depth: 100.,
+        per_section: 0.25,
+    };
+    assert_eq!(tint.amount(0., 1), 0.);
+    assert_eq!(tint.amount(50. * 32., 1), 0.5);
+    assert_eq!(tint.amount(0., 3), 0.5);
+    assert_eq!(tint.amount(100. * 32., 2), 1.);
+    assert_eq!(tint.amount(-64., 1), 0.);
+}
@@ -1,45 +1,399 @@
 use avian2d::prelude::*;
-use bevy::prelude::*;
+use bevy::{
+    prelude::*,
+    render::{
+        camera::RenderTarget,
+        render_resource::{
+            Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+        },
+        view::RenderLayers,
+    },
+    window::{PrimaryWindow, WindowMode},
+};
+use rand::Rng;
 
-use crate::player::Player;
+use crate::{map::MapBounds, player::Player};
 
+/// Renders the game world into the low res `Canvas`
 #[derive(Component)]
 pub struct MainCamera;
 
+/// Renders the `Canvas` to the window, scaled up by a whole number
+#[derive(Component)]
+struct WindowCamera;
+
+/// The sprite showing what the `MainCamera` sees
+#[derive(Component)]
+struct Canvas;
+
+/// The resolution the art is drawn at, 8px tiles in a 15 by 22.5 tile well
+pub const CANVAS_WIDTH: u32 = 120;
+pub const CANVAS_HEIGHT: u32 = 180;
+/// How many world units make up one art pixel, tiles are 32 units and 8 pixels
+pub const PIXEL_SIZE: f32 = 4.;
+/// How much of the world the `MainCamera` shows top to bottom before zooming out
+const VIEW_HEIGHT: f32 = CANVAS_HEIGHT as f32 * PIXEL_SIZE;
+/// Only the `WindowCamera` can see this layer
+const CANVAS_LAYER: RenderLayers = RenderLayers::layer(1);
+
 pub fn plugin(app: &mut App) {
-    app.add_systems(Startup, spawn_camera)
-        .add_systems(Update, camera_follow);
-}
-
-fn spawn_camera(mut commands: Commands) {
-    commands
-        .spawn((Camera2dBundle::default(), MainCamera, RigidBody::Dynamic))
-        .with_children(|p| {
-            p.spawn((
-                SpatialBundle {
-                    transform: Transform::from_translation(Vec3::X * 240.),
-                    ..default()
-                },
-                avian2d::prelude::Collider::rectangle(32., 720.),
-                avian2d::prelude::RigidBody::Static,
-            ));
-            p.spawn((
-                SpatialBundle {
-                    transform: Transform::from_translation(Vec3::X * -240.),
-                    ..default()
-                },
-                avian2d::prelude::Collider::rectangle(32., 720.),
-                avian2d::prelude::RigidBody::Static,
-            ));
-        });
+    app.register_type::<CameraRig>()
+        .register_type::<CameraFeel>()
+        .init_resource::<CameraFeel>()
+        .add_event::<CameraImpulse>()
+        .add_systems(Startup, spawn_camera)
+        .add_systems(Update, (scale_canvas, toggle_fullscreen))
+        .add_systems(
+            PostUpdate,
+            (apply_impulses, camera_follow, camera_effects)
+                .chain()
+                .before(TransformSystem::TransformPropagate),
+        )
+        .add_systems(
+            PostUpdate,
+            snap_sprites.after(TransformSystem::TransformPropagate),
+        );
+}
+
+/// How the `MainCamera` follows the player
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct CameraRig {
+    /// seconds it takes to close half the distance to the target
+    pub half_life: f32,
+    /// how far the player can move up or down from the focus before the camera follows
+    pub dead_zone: f32,
+    /// how far below the player to look when falling at `look_ahead_speed`
+    pub look_ahead: f32,
+    pub look_ahead_speed: f32,
+    /// space kept between the highest and lowest players and the edge of the view,
+    /// the camera zooms out when they are too far apart for that
+    pub margin: f32,
+    /// the height the camera is trying to center on, before look ahead
+    focus: f32,
+    /// how many times more than `VIEW_HEIGHT` is being shown
+    zoom_out: f32,
+    /// where the camera is before any shake is added
+    position: Vec2,
+}
+
+impl Default for CameraRig {
+    fn default() -> Self {
+        CameraRig {
+            half_life: 0.08,
+            dead_zone: 48.,
+            look_ahead: 128.,
+            look_ahead_speed: 250.,
+            margin: 48.,
+            focus: 0.,
+            zoom_out: 1.,
+            position: Vec2::ZERO,
+        }
+    }
+}
+
+fn spawn_camera(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let size = Extent3d {
+        width: CANVAS_WIDTH,
+        height: CANVAS_HEIGHT,
+        ..default()
+    };
+    let mut canvas = Image {
+        texture_descriptor: TextureDescriptor {
+            label: Some("canvas"),
+            size,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Bgra8UnormSrgb,
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        },
+        ..default()
+    };
+    canvas.resize(size);
+    let canvas = images.add(canvas);
+
+    let mut main = Camera2dBundle {
+        camera: Camera {
+            order: -1,
+            target: RenderTarget::Image(canvas.clone()),
+            ..default()
+        },
+        ..default()
+    };
+    main.projection.scale = PIXEL_SIZE;
+    commands.spawn((
+        Name::new("Main Camera"),
+        main,
+        MainCamera,
+        CameraRig::default(),
+        CameraEffects::default(),
+    ));
+
+    commands.spawn((
+        Name::new("Canvas"),
+        SpriteBundle {
+            texture: canvas,
+            ..default()
+        },
+        Canvas,
+        CANVAS_LAYER,
+    ));
+    commands.spawn((
+        Name::new("Window Camera"),
+        Camera2dBundle {
+            camera: Camera {
+                clear_color: ClearColorConfig::Custom(Color::BLACK),
+                ..default()
+            },
+            ..default()
+        },
+        WindowCamera,
+        IsDefaultUiCamera,
+        CANVAS_LAYER,
+    ));
+}
+
+/// Scale the canvas up by the biggest whole number that fits in the window,
+/// whatever is left over is letterboxed
+fn scale_canvas(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cameras: Query<&mut OrthographicProjection, With<WindowCamera>>,
+) {
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let fit = (window.physical_width() as f32 / CANVAS_WIDTH as f32)
+        .min(window.physical_height() as f32 / CANVAS_HEIGHT as f32)
+        .floor()
+        .max(1.);
+    let scale = window.scale_factor() / fit;
+    for mut projection in &mut cameras {
+        if projection.scale != scale {
+            projection.scale = scale;
+        }
+    }
+}
+
+fn toggle_fullscreen(
+    input: Res<ButtonInput<KeyCode>>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    let alt_enter = input.pressed(KeyCode::AltLeft) && input.just_pressed(KeyCode::Enter);
+    if !alt_enter && !input.just_pressed(KeyCode::F11) {
+        return;
+    }
+    for mut window in &mut windows {
+        window.mode = match window.mode {
+            WindowMode::Windowed => WindowMode::BorderlessFullscreen,
+            _ => WindowMode::Windowed,
+        };
+    }
+}
+
+/// sprites that moved this frame, apart from the canvas itself
+type Moved = (Changed<GlobalTransform>, With<Sprite>, Without<Canvas>);
+
+/// Keep everything the `MainCamera` draws lined up with the canvas pixels
+fn snap_sprites(mut sprites: Query<&mut GlobalTransform, Moved>) {
+    for mut transform in &mut sprites {
+        let mut affine = transform.affine();
+        affine.translation.x = (affine.translation.x / PIXEL_SIZE).round() * PIXEL_SIZE;
+        affine.translation.y = (affine.translation.y / PIXEL_SIZE).round() * PIXEL_SIZE;
+        *transform = GlobalTransform::from(affine);
+    }
 }
 
+type Rig = (
+    &'static mut Transform,
+    &'static mut CameraRig,
+    &'static OrthographicProjection,
+);
+
 fn camera_follow(
-    player: Query<&Transform, (With<Player>, Without<MainCamera>)>,
-    mut camera: Query<&mut Transform, With<MainCamera>>,
+    players: Query<(&Transform, &LinearVelocity), With<Player>>,
+    mut camera: Query<Rig, (With<MainCamera>, Without<Player>)>,
+    bounds: Query<&MapBounds>,
+    time: Res<Time>,
+) {
+    if players.is_empty() {
+        return;
+    }
+    let Ok((mut camera, mut rig, projection)) = camera.get_single_mut() else {
+        return;
+    };
+
+    // frame the middle of every player, looking ahead by how fast they fall on average
+    let (mut low, mut high, mut fall) = (f32::MAX, f32::MIN, 0.);
+    for (player, velocity) in &players {
+        low = low.min(player.translation.y);
+        high = high.max(player.translation.y);
+        fall -= velocity.y;
+    }
+    let y = (low + high) / 2.;
+    rig.focus = rig.focus.clamp(y - rig.dead_zone, y + rig.dead_zone);
+    let fall = fall / players.iter().len() as f32;
+    let falling = (fall / rig.look_ahead_speed).clamp(0., 1.);
+    let (zoom_out, y) = frame(low, high, rig.focus - falling * rig.look_ahead, rig.margin);
+    let mut target = Vec2::new(0., y);
+
+    if let Ok(bounds) = bounds.get_single() {
+        let half = projection.area.size() / 2.;
+        target.x = clamp_or_center(target.x, bounds.0.min.x + half.x, bounds.0.max.x - half.x);
+        target.y = clamp_or_center(target.y, bounds.0.min.y + half.y, bounds.0.max.y - half.y);
+    }
+
+    let t = 1. - 0.5f32.powf(time.delta_seconds() / rig.half_life);
+    rig.position = rig.position.lerp(target, t);
+    rig.zoom_out = rig.zoom_out.lerp(zoom_out, t);
+    camera.translation =
+        ((rig.position / PIXEL_SIZE).round() * PIXEL_SIZE).extend(camera.translation.z);
+}
+
+/// How far to zoom out and where to center so everything from `low` to `high` is
+/// `margin` inside the view, staying as close to `y` as that allows
+fn frame(low: f32, high: f32, y: f32, margin: f32) -> (f32, f32) {
+    let zoom_out = ((high - low + 2. * margin) / VIEW_HEIGHT).max(1.);
+    let half = VIEW_HEIGHT * zoom_out / 2.;
+    (zoom_out, y.clamp(high + margin - half, low - margin + half))
+}
+
+#[test]
+fn every_player_stays_in_view() {
+    // one player, the look ahead and dead zone are left alone
+    assert_eq!(frame(0., 0., -128., 48.), (1., -128.));
+    // a little apart, the view shifts to keep the top player in
+    let (zoom_out, y) = frame(-500., 0., -628., 48.);
+    assert_eq!(zoom_out, 1.);
+    assert!(y + VIEW_HEIGHT / 2. >= 48.);
+    // too far apart for one screen, zoom out just enough to show both
+    let (zoom_out, y) = frame(-2000., 0., -2128., 48.);
+    assert!(zoom_out > 1.);
+    assert_eq!(y, -1000.);
+    assert_eq!(VIEW_HEIGHT * zoom_out, 2096.);
+}
+
+/// Send to make the `MainCamera` react to something happening in game
+#[derive(Event, Clone, Copy, Debug)]
+pub enum CameraImpulse {
+    /// add trauma from 0 to 1, the camera shakes with trauma squared
+    Shake(f32),
+    /// zoom in by this fraction of the view then spring back out
+    ZoomPunch(f32),
+    /// stop game time for this many seconds
+    Freeze(f32),
+}
+
+/// Global settings for how strong `CameraImpulse`s are
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+pub struct CameraFeel {
+    /// scales every shake and zoom, 0 turns them off
+    pub intensity: f32,
+    /// only ever moves the camera, turning it would smear the canvas pixels
+    pub max_offset: f32,
+    /// trauma lost per second
+    pub trauma_decay: f32,
+    /// zoom lost per second
+    pub zoom_decay: f32,
+    pub freeze_frames: bool,
+}
+
+impl Default for CameraFeel {
+    fn default() -> Self {
+        CameraFeel {
+            intensity: 1.,
+            max_offset: 16.,
+            trauma_decay: 1.5,
+            zoom_decay: 0.5,
+            freeze_frames: true,
+        }
+    }
+}
+
+#[derive(Component, Default)]
+pub(crate) struct CameraEffects {
+    trauma: f32,
+    zoom: f32,
+    freeze: f32,
+    /// true if we paused virtual time and need to unpause it
+    frozen: bool,
+}
+
+fn apply_impulses(
+    mut events: EventReader<CameraImpulse>,
+    mut cameras: Query<&mut CameraEffects, With<MainCamera>>,
+    feel: Res<CameraFeel>,
+) {
+    for event in events.read() {
+        for mut effects in &mut cameras {
+            match *event {
+                CameraImpulse::Shake(trauma) => {
+                    effects.trauma = (effects.trauma + trauma * feel.intensity).clamp(0., 1.);
+                }
+                CameraImpulse::ZoomPunch(zoom) => {
+                    effects.zoom = effects.zoom.max(zoom * feel.intensity).clamp(0., 0.5);
+                }
+                CameraImpulse::Freeze(seconds) if feel.freeze_frames => {
+                    effects.freeze = effects.freeze.max(seconds);
+                }
+                CameraImpulse::Freeze(_) => {}
+            }
+        }
+    }
+}
+
+type Effected = (
+    &'static mut CameraEffects,
+    &'static mut Transform,
+    &'static mut OrthographicProjection,
+    Option<&'static CameraRig>,
+);
+
+/// Effects decay in real time so they keep playing out during a freeze frame
+pub(crate) fn camera_effects(
+    mut cameras: Query<Effected>,
+    feel: Res<CameraFeel>,
+    real: Res<Time<Real>>,
+    mut game_time: ResMut<Time<Virtual>>,
 ) {
-    let player = player.single();
-    let mut camera = camera.single_mut();
+    let dt = real.delta_seconds();
+    let mut rng = rand::thread_rng();
+    for (mut effects, mut transform, mut projection, rig) in &mut cameras {
+        if effects.freeze > 0. {
+            if !effects.frozen && !game_time.is_paused() {
+                game_time.pause();
+                effects.frozen = true;
+            }
+            effects.freeze -= dt;
+        } else if effects.frozen {
+            game_time.unpause();
+            effects.frozen = false;
+        }
+
+        let shake = effects.trauma * effects.trauma;
+        let offset = Vec2::new(
+            feel.max_offset * shake * rng.gen_range(-1. ..1.),
+            feel.max_offset * shake * rng.gen_range(-1. ..1.),
+        );
+        // whole pixels only so the world does not swim against the canvas
+        transform.translation += ((offset / PIXEL_SIZE).round() * PIXEL_SIZE).extend(0.);
+        let zoom_out = rig.map_or(1., |rig| rig.zoom_out);
+        projection.scale = PIXEL_SIZE * zoom_out * (1. - effects.zoom);
+
+        effects.trauma = (effects.trauma - feel.trauma_decay * dt).max(0.);
+        effects.zoom = (effects.zoom - feel.zoom_decay * dt).max(0.);
+    }
+}
 
-    camera.translation = camera.translation.lerp(player.translation.y * Vec3::Y, 0.5);
+/// When the view is bigger than the bounds there is nothing to clamp to, so stay in the middle
+fn clamp_or_center(value: f32, min: f32, max: f32) -> f32 {
+    if min > max {
+        (min + max) / 2.
+    } else {
+        value.clamp(min, max)
+    }
 }
@@ -0,0 +1,179 @@
+//! Launch options, read from the command line with environment variables as a fallback
+
+use std::{ffi::OsString, fmt::Write};
+
+use crate::seed::Seed;
+
+const HELP: &str = "\
+Usage: downwell_clone [OPTIONS]
+
+Options:
+  --map <PATH>         map to start on, relative to assets/      [WOWNDELL_MAP]
+  --seed <SEED>        seed for every run, a number, a shared    [WOWNDELL_SEED]
+                       code like K3F9-QX2A or any phrase
+  --daily              play today's seed                         [WOWNDELL_DAILY]
+  --fullscreen         start in borderless fullscreen            [WOWNDELL_FULLSCREEN]
+  --windowed           start in a window
+  --editor             show the editor                           [WOWNDELL_EDITOR]
+  --no-editor
+  --physics-debug      draw colliders and contacts               [WOWNDELL_PHYSICS_DEBUG]
+  --no-physics-debug
+  -h, --help           print this and quit
+
+Switches set by environment variables take 1/true/on or 0/false/off.
+The editor and physics debug are on by default in debug builds.";
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Options {
+    pub map: Option<String>,
+    pub seed: Option<Seed>,
+    pub daily: bool,
+    pub fullscreen: bool,
+    pub editor: bool,
+    pub physics_debug: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            map: None,
+            seed: None,
+            daily: false,
+            fullscreen: false,
+            editor: cfg!(debug_assertions),
+            physics_debug: cfg!(debug_assertions),
+        }
+    }
+}
+
+impl Options {
+    /// Read the options this process was started with, printing usage and quitting
+    /// if they are wrong or `--help` was asked for
+    pub fn from_env() -> Self {
+        let env = |name: &str| std::env::var(name).ok();
+        match Options::parse(std::env::args_os().skip(1), env) {
+            Ok(Some(options)) => options,
+            Ok(None) => {
+                println!("{HELP}");
+                std::process::exit(0);
+            }
+            Err(e) => {
+                eprintln!("{e}\n\n{HELP}");
+                std::process::exit(2);
+            }
+        }
+    }
+
+    /// Environment variables are read first so anything on the command line wins,
+    /// `None` means help was asked for
+    fn parse(
+        args: impl IntoIterator<Item = OsString>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Option<Options>, String> {
+        let mut options = Options::default();
+        if let Some(map) = env("WOWNDELL_MAP") {
+            options.map = Some(map);
+        }
+        if let Some(seed) = env("WOWNDELL_SEED") {
+            options.seed = Some(parse_seed(&seed).map_err(|e| format!("WOWNDELL_SEED: {e}"))?);
+        }
+        for (name, flag) in [
+            ("WOWNDELL_DAILY", &mut options.daily),
+            ("WOWNDELL_FULLSCREEN", &mut options.fullscreen),
+            ("WOWNDELL_EDITOR", &mut options.editor),
+            ("WOWNDELL_PHYSICS_DEBUG", &mut options.physics_debug),
+        ] {
+            if let Some(value) = env(name) {
+                *flag = parse_switch(&value).map_err(|e| format!("{name}: {e}"))?;
+            }
+        }
+
+        let mut args = args
+            .into_iter()
+            .map(|arg| arg.to_string_lossy().into_owned());
+        while let Some(arg) = args.next() {
+            // `--name=value` works as well as `--name value`
+            let (name, inline) = match arg.split_once('=') {
+                Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("{name} needs a value"))
+            };
+            match name.as_str() {
+                "--map" => options.map = Some(value()?),
+                "--seed" => {
+                    options.seed = Some(parse_seed(&value()?).map_err(|e| format!("{name}: {e}"))?)
+                }
+                "--daily" => options.daily = true,
+                "--fullscreen" => options.fullscreen = true,
+                "--windowed" => options.fullscreen = false,
+                "--editor" => options.editor = true,
+                "--no-editor" => options.editor = false,
+                "--physics-debug" => options.physics_debug = true,
+                "--no-physics-debug" => options.physics_debug = false,
+                "-h" | "--help" => return Ok(None),
+                _ => {
+                    let mut e = format!("unknown option {name}");
+                    if !name.starts_with('-') {
+                        let _ = write!(e, ", did you mean --map {name}?");
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(Some(options))
+    }
+}
+
+fn parse_seed(value: &str) -> Result<Seed, String> {
+    Seed::parse(value)
+}
+
+fn parse_switch(value: &str) -> Result<bool, String> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "on" | "yes" => Ok(true),
+        "0" | "false" | "off" | "no" | "" => Ok(false),
+        _ => Err(format!("{value:?} is not on or off")),
+    }
+}
+
+#[cfg(test)]
+fn parse(args: &[&str], env: &[(&str, &str)]) -> Result<Option<Options>, String> {
+    Options::parse(args.iter().map(OsString::from), |name| {
+        env.iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.to_string())
+    })
+}
+
+#[test]
+fn command_line_beats_environment() {
+    let options = parse(
+        &["--map", "maps/well.map", "--seed=7", "--no-editor"],
+        &[
+            ("WOWNDELL_MAP", "maps/lobby.map"),
+            ("WOWNDELL_SEED", "3"),
+            ("WOWNDELL_EDITOR", "1"),
+            ("WOWNDELL_FULLSCREEN", "true"),
+        ],
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(options.map.as_deref(), Some("maps/well.map"));
+    assert_eq!(options.seed, Some(Seed(7)));
+    assert!(!options.editor);
+    assert!(options.fullscreen);
+}
+
+#[test]
+fn bad_options_are_errors() {
+    assert!(parse(&["--seed", ""], &[]).is_err());
+    assert!(parse(&["--seed"], &[]).is_err());
+    assert!(parse(&["--jump-higher"], &[]).is_err());
+    assert!(parse(&[], &[("WOWNDELL_EDITOR", "maybe")]).is_err());
+    assert_eq!(parse(&["--help"], &[]), Ok(None));
+}
@@ -0,0 +1,130 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{
+    destructible::{damage_tiles, TileBroken},
+    game_state::RunStats,
+    gunboots::Gunboots,
+    map::Gameplay,
+    player::{Grounded, Player, Wallet},
+};
+
+pub fn plugin(app: &mut App) {
+    app.add_event::<ComboEnded>().add_systems(
+        FixedUpdate,
+        count_combo.after(damage_tiles).in_set(Gameplay),
+    );
+}
+
+/// Blocks a player has broken since they last stood on something
+#[derive(Component, Default)]
+pub struct Combo(pub u32);
+
+#[derive(Clone, Copy, Debug)]
+enum ComboReward {
+    Coins(u32),
+    MaxAmmo(u8),
+}
+
+/// Paid out when a combo ends, for every threshold the combo reached
+const COMBO_REWARDS: [(u32, ComboReward); 5] = [
+    (5, ComboReward::Coins(5)),
+    (8, ComboReward::MaxAmmo(1)),
+    (12, ComboReward::Coins(20)),
+    (16, ComboReward::MaxAmmo(1)),
+    (25, ComboReward::Coins(50)),
+];
+
+/// Sent when a player lands or dies and their combo is over, along with what it paid out
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ComboEnded {
+    pub player: Entity,
+    pub count: u32,
+    pub coins: u32,
+    pub max_ammo: u8,
+}
+
+/// Ends combos and pays out whatever they earned
+#[derive(SystemParam)]
+pub struct ComboPayout<'w> {
+    ended: EventWriter<'w, ComboEnded>,
+    stats: ResMut<'w, RunStats>,
+}
+
+impl ComboPayout<'_> {
+    pub fn end(
+        &mut self,
+        player: Entity,
+        combo: &mut Combo,
+        wallet: &mut Wallet,
+        gunboots: &mut Gunboots,
+    ) {
+        if combo.0 == 0 {
+            return;
+        }
+        let mut result = ComboEnded {
+            player,
+            count: combo.0,
+            coins: 0,
+            max_ammo: 0,
+        };
+        for (threshold, reward) in COMBO_REWARDS {
+            if combo.0 < threshold {
+                break;
+            }
+            match reward {
+                ComboReward::Coins(coins) => result.coins += coins,
+                ComboReward::MaxAmmo(ammo) => result.max_ammo += ammo,
+            }
+        }
+        if result.coins > 0 {
+            wallet.0 += result.coins;
+            self.stats.coins += result.coins;
+        }
+        if result.max_ammo > 0 {
+            gunboots.max_ammo = gunboots.max_ammo.saturating_add(result.max_ammo);
+        }
+        combo.0 = 0;
+        self.ended.send(result);
+    }
+}
+
+type Comboing = (
+    Entity,
+    &'static mut Combo,
+    Option<Ref<'static, Grounded>>,
+    &'static mut Wallet,
+    &'static mut Gunboots,
+);
+
+/// Breaking a block adds to the breaker's combo, landing on anything that did not
+/// break underfoot ends it
+pub(crate) fn count_combo(
+    mut broken: EventReader<TileBroken>,
+    mut players: Query<Comboing, With<Player>>,
+    mut payout: ComboPayout,
+) {
+    let mut breakers = Vec::new();
+    for event in broken.read() {
+        let Some(player) = event.by else {
+            continue;
+        };
+        let Ok((_, mut combo, grounded, ..)) = players.get_mut(player) else {
+            continue;
+        };
+        // only blocks broken in the air count, besides the one just stomped
+        if grounded.is_some_and(|grounded| !grounded.is_added()) {
+            continue;
+        }
+        combo.0 += 1;
+        breakers.push(player);
+    }
+    for (player, mut combo, grounded, mut wallet, mut gunboots) in &mut players {
+        // the block just stomped still counts as ground until physics catches up,
+        // so only the landing itself can end a combo
+        let landed = grounded.is_some_and(|grounded| grounded.is_added());
+        if !landed || breakers.contains(&player) {
+            continue;
+        }
+        payout.end(player, &mut combo, &mut wallet, &mut gunboots);
+    }
+}
@@ -0,0 +1,411 @@
+use std::{fs, path::PathBuf};
+
+use bevy::{input::gamepad::GamepadSettings, prelude::*};
+use leafwing_input_manager::prelude::*;
+use strum::IntoEnumIterator;
+
+use crate::{
+    player::{PlayerAction, PlayerDevice},
+    save::write_atomic,
+};
+
+pub fn plugin(app: &mut App) {
+    app.insert_resource(Controls::load())
+        .init_resource::<RebindMenu>()
+        .add_systems(Startup, set_stick_deadzone)
+        .add_systems(
+            Update,
+            (
+                toggle_rebind_menu,
+                // before navigating so the press that picks a row is not bound
+                rebind,
+                navigate_rebind_menu,
+                update_rebind_menu,
+                apply_controls,
+            )
+                .chain()
+                .in_set(ControlsMenu),
+        );
+}
+
+/// Everything the controls menu does each frame, other menus go before it
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ControlsMenu;
+
+/// How far a stick has to be pushed before it counts as a direction
+const STICK_DEADZONE: f32 = 0.3;
+
+/// The keys and buttons every player uses, loaded from and saved to `controls.ron`
+/// in the platform config directory.
+/// `InputMap` can not be deserialized so this is stored instead and turned into one
+#[derive(Resource, serde::Deserialize, serde::Serialize, Clone, PartialEq, Debug)]
+pub struct Controls {
+    keys: Vec<(PlayerAction, KeyCode)>,
+    buttons: Vec<(PlayerAction, GamepadButtonType)>,
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Controls {
+            keys: vec![
+                (PlayerAction::MoveLeft, KeyCode::KeyA),
+                (PlayerAction::MoveRight, KeyCode::KeyD),
+                (PlayerAction::Jump, KeyCode::KeyW),
+                (PlayerAction::Down, KeyCode::KeyS),
+                (PlayerAction::MoveLeft, KeyCode::ArrowLeft),
+                (PlayerAction::MoveRight, KeyCode::ArrowRight),
+                (PlayerAction::Jump, KeyCode::ArrowUp),
+                (PlayerAction::Down, KeyCode::ArrowDown),
+            ],
+            buttons: vec![
+                (PlayerAction::MoveLeft, GamepadButtonType::DPadLeft),
+                (PlayerAction::MoveRight, GamepadButtonType::DPadRight),
+                (PlayerAction::Jump, GamepadButtonType::South),
+                (PlayerAction::Jump, GamepadButtonType::East),
+                (PlayerAction::Down, GamepadButtonType::DPadDown),
+            ],
+        }
+    }
+}
+
+impl Controls {
+    /// Load the saved controls, falling back to the defaults if there are none or they are broken
+    pub fn load() -> Self {
+        let Some(path) = config_path() else {
+            return Controls::default();
+        };
+        let Ok(data) = fs::read_to_string(&path) else {
+            return Controls::default();
+        };
+        match ron::from_str(&data) {
+            Ok(controls) => controls,
+            Err(e) => {
+                error!("{:?}: {e}", path);
+                Controls::default()
+            }
+        }
+    }
+
+    fn save(&self) {
+        let Some(path) = config_path() else {
+            return;
+        };
+        match ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new()) {
+            Ok(data) => {
+                if let Err(e) = write_atomic(&path, data.as_bytes()) {
+                    error!("failed to write {:?}: {e}", path);
+                }
+            }
+            Err(e) => error!("{e}"),
+        }
+    }
+
+    /// Only the bindings for `device`, gamepads also always get the left stick
+    pub fn input_map(&self, device: PlayerDevice) -> InputMap<PlayerAction> {
+        match device {
+            PlayerDevice::Keyboard => InputMap::new(self.keys.clone()),
+            PlayerDevice::Gamepad(gamepad) => self.gamepad_map().with_gamepad(gamepad),
+            // without a gamepad set, leafwing reads from all of them
+            PlayerDevice::Any => self.gamepad_map().with_multiple(self.keys.clone()),
+        }
+    }
+
+    fn gamepad_map(&self) -> InputMap<PlayerAction> {
+        InputMap::new(self.buttons.clone()).with_multiple([
+            (PlayerAction::MoveLeft, GamepadControlDirection::LEFT_LEFT),
+            (PlayerAction::MoveRight, GamepadControlDirection::LEFT_RIGHT),
+            (PlayerAction::Down, GamepadControlDirection::LEFT_DOWN),
+        ])
+    }
+}
+
+/// Bind `input` to `action` in place of its first binding, keeping any others it has,
+/// and take `input` off every other action
+fn replace_binding<T: PartialEq>(
+    bindings: &mut Vec<(PlayerAction, T)>,
+    action: PlayerAction,
+    input: T,
+) {
+    bindings.retain(|(bound, old)| *bound == action || *old != input);
+    if bindings
+        .iter()
+        .any(|(bound, old)| *bound == action && *old == input)
+    {
+        return;
+    }
+    match bindings.iter_mut().find(|(bound, _)| *bound == action) {
+        Some(binding) => binding.1 = input,
+        None => bindings.push((action, input)),
+    }
+}
+
+fn names<T: std::fmt::Debug>(bindings: &[(PlayerAction, T)], action: PlayerAction) -> Vec<String> {
+    bindings
+        .iter()
+        .filter(|(bound, _)| *bound == action)
+        .map(|(_, input)| format!("{input:?}"))
+        .collect()
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("wowndell").join("controls.ron"))
+}
+
+fn set_stick_deadzone(mut settings: ResMut<GamepadSettings>) {
+    let axis = &mut settings.default_axis_settings;
+    axis.set_deadzone_lowerbound(-STICK_DEADZONE);
+    axis.set_deadzone_upperbound(STICK_DEADZONE);
+}
+
+/// Push the current `Controls` onto every player whenever they change
+fn apply_controls(
+    controls: Res<Controls>,
+    mut players: Query<(&PlayerDevice, &mut InputMap<PlayerAction>)>,
+) {
+    if !controls.is_changed() {
+        return;
+    }
+    for (device, mut map) in &mut players {
+        *map = controls.input_map(*device);
+    }
+}
+
+#[derive(Resource, Default)]
+pub(crate) struct RebindMenu {
+    root: Option<Entity>,
+    selected: usize,
+    /// waiting for the next key or button to bind to this action
+    listening: Option<PlayerAction>,
+}
+
+/// Other menus ignore input while the controls menu is open
+pub(crate) fn rebind_menu_closed(menu: Res<RebindMenu>) -> bool {
+    menu.root.is_none()
+}
+
+/// One row in the menu, the last row resets everything
+#[derive(Component)]
+struct RebindRow {
+    /// position from the top, what `RebindMenu::selected` points at
+    index: usize,
+    action: Option<PlayerAction>,
+}
+
+#[derive(Component)]
+struct RebindLabel;
+
+/// F1 or select opens and closes the controls menu, players can not move while it is open
+fn toggle_rebind_menu(
+    mut commands: Commands,
+    mut menu: ResMut<RebindMenu>,
+    keys: Res<ButtonInput<KeyCode>>,
+    buttons: Res<ButtonInput<GamepadButton>>,
+    mut players: Query<&mut ActionState<PlayerAction>>,
+) {
+    let select = buttons
+        .get_just_pressed()
+        .any(|button| button.button_type == GamepadButtonType::Select);
+    let escape =
+        menu.root.is_some() && menu.listening.is_none() && keys.just_pressed(KeyCode::Escape);
+    if !keys.just_pressed(KeyCode::F1) && !select && !escape {
+        return;
+    }
+    if let Some(root) = menu.root.take() {
+        commands.entity(root).despawn_recursive();
+        menu.listening = None;
+        for mut action_state in &mut players {
+            action_state.enable();
+        }
+        return;
+    }
+    for mut action_state in &mut players {
+        action_state.reset_all();
+        action_state.disable();
+    }
+    let root = commands
+        .spawn((
+            Name::new("Controls Menu"),
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(8.),
+                    ..default()
+                },
+                background_color: Color::srgba(0., 0., 0., 0.8).into(),
+                ..default()
+            },
+        ))
+        .with_children(|menu| {
+            menu.spawn(TextBundle::from_section(
+                "Controls",
+                TextStyle {
+                    font_size: 32.,
+                    ..default()
+                },
+            ));
+            for (index, action) in PlayerAction::iter().map(Some).chain([None]).enumerate() {
+                menu.spawn((
+                    ButtonBundle {
+                        style: Style {
+                            width: Val::Percent(90.),
+                            padding: UiRect::all(Val::Px(4.)),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    RebindRow { index, action },
+                ))
+                .with_children(|button| {
+                    button.spawn((
+                        TextBundle::from_section("", TextStyle::default()),
+                        RebindLabel,
+                    ));
+                });
+            }
+        })
+        .id();
+    menu.root = Some(root);
+    menu.selected = 0;
+}
+
+/// Up and down move between rows, enter or a face button picks one, the mouse also works
+fn navigate_rebind_menu(
+    mut menu: ResMut<RebindMenu>,
+    mut controls: ResMut<Controls>,
+    keys: Res<ButtonInput<KeyCode>>,
+    buttons: Res<ButtonInput<GamepadButton>>,
+    rows: Query<(&Interaction, &RebindRow)>,
+) {
+    if menu.root.is_none() || menu.listening.is_some() {
+        return;
+    }
+    let count = PlayerAction::iter().count() + 1;
+    let pressed = |key: KeyCode, button: GamepadButtonType| {
+        keys.just_pressed(key) || buttons.get_just_pressed().any(|b| b.button_type == button)
+    };
+    if pressed(KeyCode::ArrowUp, GamepadButtonType::DPadUp) {
+        menu.selected = (menu.selected + count - 1) % count;
+    }
+    if pressed(KeyCode::ArrowDown, GamepadButtonType::DPadDown) {
+        menu.selected = (menu.selected + 1) % count;
+    }
+    let mut chosen = pressed(KeyCode::Enter, GamepadButtonType::South).then_some(menu.selected);
+    for (interaction, row) in &rows {
+        match interaction {
+            Interaction::Pressed => chosen = Some(row.index),
+            Interaction::Hovered => menu.selected = row.index,
+            Interaction::None => {}
+        }
+    }
+    let Some(row) = chosen.and_then(|chosen| rows.iter().find(|(_, row)| row.index == chosen))
+    else {
+        return;
+    };
+    match row.1.action {
+        Some(action) => menu.listening = Some(action),
+        None => {
+            *controls = Controls::default();
+            controls.save();
+        }
+    }
+}
+
+/// Bind the next key or gamepad button pressed, escape cancels
+fn rebind(
+    mut menu: ResMut<RebindMenu>,
+    mut controls: ResMut<Controls>,
+    keys: Res<ButtonInput<KeyCode>>,
+    buttons: Res<ButtonInput<GamepadButton>>,
+) {
+    let Some(action) = menu.listening else {
+        return;
+    };
+    if keys.just_pressed(KeyCode::Escape) {
+        menu.listening = None;
+        return;
+    }
+    if let Some(&key) = keys.get_just_pressed().next() {
+        replace_binding(&mut controls.keys, action, key);
+    } else if let Some(button) = buttons.get_just_pressed().next() {
+        replace_binding(&mut controls.buttons, action, button.button_type);
+    } else {
+        return;
+    }
+    controls.save();
+    menu.listening = None;
+}
+
+fn update_rebind_menu(
+    menu: Res<RebindMenu>,
+    controls: Res<Controls>,
+    mut rows: Query<(&RebindRow, &Children, &mut BackgroundColor)>,
+    mut labels: Query<&mut Text, With<RebindLabel>>,
+) {
+    if menu.root.is_none() {
+        return;
+    }
+    for (row, children, mut background) in &mut rows {
+        let selected = row.index == menu.selected;
+        let color = if selected {
+            Color::srgb(0.4, 0.2, 0.3)
+        } else {
+            Color::srgb(0.15, 0.15, 0.15)
+        };
+        if background.0 != color {
+            background.0 = color;
+        }
+        let text = match row.action {
+            Some(action) if menu.listening == Some(action) => {
+                format!("{action:?}: press a key or button...")
+            }
+            Some(action) => {
+                let mut bound = names(&controls.keys, action);
+                // sticks are not listed since they can not be rebound
+                bound.extend(names(&controls.buttons, action));
+                format!("{action:?}: {}", bound.join(", "))
+            }
+            None => "Reset to defaults".to_string(),
+        };
+        for &child in children {
+            if let Ok(mut label) = labels.get_mut(child) {
+                if label.sections[0].value != text {
+                    label.sections[0].value = text.clone();
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn controls_round_trip() {
+    let mut controls = Controls::default();
+    replace_binding(&mut controls.keys, PlayerAction::Jump, KeyCode::Space);
+    replace_binding(
+        &mut controls.buttons,
+        PlayerAction::Down,
+        GamepadButtonType::South,
+    );
+    let data = ron::ser::to_string_pretty(&controls, ron::ser::PrettyConfig::new()).unwrap();
+    let loaded: Controls = ron::from_str(&data).unwrap();
+    assert_eq!(controls, loaded);
+    // only the first binding is replaced, the arrow keys still work
+    assert_eq!(
+        names(&loaded.keys, PlayerAction::Jump),
+        ["Space", "ArrowUp"]
+    );
+    assert_eq!(
+        names(&loaded.keys, PlayerAction::MoveLeft),
+        ["KeyA", "ArrowLeft"]
+    );
+    // south moved from jump to down, east still jumps
+    assert_eq!(names(&loaded.buttons, PlayerAction::Jump), ["East"]);
+    assert_eq!(names(&loaded.buttons, PlayerAction::Down), ["South"]);
+    // binding something an action already has changes nothing
+    let before = controls.clone();
+    replace_binding(&mut controls.keys, PlayerAction::Jump, KeyCode::ArrowUp);
+    assert_eq!(controls, before);
+}
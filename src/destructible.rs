@@ -0,0 +1,213 @@
+use avian2d::prelude::*;
+use bevy::prelude::*;
+use rand::Rng;
+use strum::IntoEnumIterator;
+
+use crate::{
+    audio::Sound,
+    camera::CameraImpulse,
+    game_state::{despawn_all, RunStats, StartRun},
+    map::{Adjacencies, Gameplay, MapEntities, Tile, TileId, TileSprite},
+    particles::{Burst, DEBRIS},
+    player::{update_grounded, Grounded, Player, PlayerStats, Wallet},
+    seed::GameRng,
+};
+
+pub fn plugin(app: &mut App) {
+    app.add_event::<DamageTile>()
+        .add_event::<TileBroken>()
+        .add_systems(
+            FixedUpdate,
+            (
+                stomp,
+                damage_tiles,
+                forget_broken_tiles,
+                magnet_coins,
+                collect_coins,
+            )
+                .chain()
+                // so a stomp lands on the same tick as the player
+                .after(update_grounded)
+                .in_set(Gameplay),
+        )
+        .add_systems(StartRun, despawn_all::<Coin>);
+}
+
+/// A tile that breaks after taking `health` damage, dropping `coins` when it does
+#[derive(Component)]
+pub struct Destructible {
+    pub health: u8,
+    pub coins: u8,
+}
+
+/// Send to hurt a tile, does nothing if the tile is not `Destructible`
+#[derive(Event)]
+pub struct DamageTile {
+    pub tile: Entity,
+    pub damage: u8,
+    /// the player who did it, if it was a player
+    pub by: Option<Entity>,
+}
+
+/// Sent when a `Destructible` runs out of health
+#[derive(Event)]
+pub struct TileBroken {
+    pub tile: Entity,
+    pub id: IVec3,
+    pub by: Option<Entity>,
+}
+
+#[derive(Component)]
+pub struct Coin;
+
+/// How fast a magnet pulls coins in
+const MAGNET_SPEED: f32 = 300.;
+
+/// players that touched down this tick
+type Landed = (With<Player>, Added<Grounded>);
+
+/// Landing on a block counts as a hit
+fn stomp(
+    players: Query<(Entity, &ShapeHits), Landed>,
+    blocks: Query<(), With<Destructible>>,
+    mut damage: EventWriter<DamageTile>,
+    mut camera: EventWriter<CameraImpulse>,
+    mut sounds: EventWriter<Sound>,
+) {
+    for (player, hits) in &players {
+        for hit in hits.iter() {
+            if blocks.contains(hit.entity) {
+                damage.send(DamageTile {
+                    tile: hit.entity,
+                    damage: 1,
+                    by: Some(player),
+                });
+                camera.send(CameraImpulse::Shake(0.25));
+                sounds.send(Sound::Stomp);
+            }
+        }
+    }
+}
+
+pub(crate) fn damage_tiles(
+    mut commands: Commands,
+    mut events: EventReader<DamageTile>,
+    mut broken: EventWriter<TileBroken>,
+    mut blocks: Query<(&mut Destructible, &TileId, &GlobalTransform)>,
+    mut camera: EventWriter<CameraImpulse>,
+    mut bursts: EventWriter<Burst>,
+    mut game_rng: ResMut<GameRng>,
+) {
+    for event in events.read() {
+        let Ok((mut block, id, transform)) = blocks.get_mut(event.tile) else {
+            continue;
+        };
+        if block.health == 0 {
+            // already broke this frame
+            continue;
+        }
+        block.health = block.health.saturating_sub(event.damage);
+        if block.health > 0 {
+            continue;
+        }
+        commands.entity(event.tile).despawn_recursive();
+        broken.send(TileBroken {
+            tile: event.tile,
+            id: id.0,
+            by: event.by,
+        });
+        camera.send(CameraImpulse::Shake(0.3));
+        camera.send(CameraImpulse::Freeze(0.04));
+
+        let position = transform.translation();
+        bursts.send(Burst {
+            emitter: DEBRIS,
+            at: position.truncate(),
+        });
+        for _ in 0..block.coins {
+            commands.spawn((
+                Name::new("Coin"),
+                Coin,
+                SpriteBundle {
+                    transform: Transform::from_translation(position),
+                    sprite: Sprite {
+                        custom_size: Some(Vec2::splat(16.)),
+                        ..default()
+                    },
+                    visibility: Visibility::Hidden,
+                    ..default()
+                },
+                TextureAtlas::default(),
+                TileSprite::Coin,
+                RigidBody::Dynamic,
+                LockedAxes::ROTATION_LOCKED,
+                Collider::circle(6.),
+                LinearVelocity(Vec2::new(
+                    game_rng.gen_range(-60. ..60.),
+                    game_rng.gen_range(100. ..200.),
+                )),
+            ));
+        }
+    }
+}
+
+/// Take broken tiles out of the map and have their neighbours pick new sprites
+fn forget_broken_tiles(
+    mut broken: EventReader<TileBroken>,
+    mut maps: Query<&mut MapEntities>,
+    mut tiles: Query<&mut Tile>,
+) {
+    for event in broken.read() {
+        for mut map in &mut maps {
+            if map.get(event.id) != Some(event.tile) {
+                continue;
+            }
+            map.remove(event.id);
+            for adjacent in Adjacencies::iter() {
+                if let Some(neighbour) = map.get(event.id + adjacent) {
+                    if let Ok(mut tile) = tiles.get_mut(neighbour) {
+                        tile.set_changed();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Coins inside a player's magnet radius fly at the nearest such player
+fn magnet_coins(
+    players: Query<(&Transform, &PlayerStats), With<Player>>,
+    mut coins: Query<(&Transform, &mut LinearVelocity), With<Coin>>,
+) {
+    for (coin, mut velocity) in &mut coins {
+        let coin = coin.translation.truncate();
+        let nearest = players
+            .iter()
+            .map(|(player, stats)| (player.translation.truncate() - coin, stats.magnet_radius))
+            .filter(|(offset, radius)| offset.length() < *radius)
+            .min_by(|(a, _), (b, _)| a.length().total_cmp(&b.length()));
+        if let Some((offset, _)) = nearest {
+            velocity.0 = offset.normalize_or_zero() * MAGNET_SPEED;
+        }
+    }
+}
+
+/// Coins go to whoever touches them first
+fn collect_coins(
+    mut commands: Commands,
+    mut players: Query<(&CollidingEntities, &mut Wallet), With<Player>>,
+    coins: Query<(), With<Coin>>,
+    mut stats: ResMut<RunStats>,
+    mut sounds: EventWriter<Sound>,
+) {
+    for (touching, mut wallet) in &mut players {
+        for &coin in touching.iter() {
+            if coins.contains(coin) {
+                commands.entity(coin).despawn_recursive();
+                wallet.0 += 1;
+                stats.coins += 1;
+                sounds.send(Sound::Coin);
+            }
+        }
+    }
+}
@@ -4,14 +4,17 @@ use bevy_editor_pls::egui::Slider;
 use bevy_inspector_egui::reflect_inspector;
 
 use bevy::prelude::*;
-use bevy_editor_pls::{
-    editor_window::EditorWindow, AddEditorWindow,
-};
+use bevy_editor_pls::{editor_window::EditorWindow, AddEditorWindow};
 
-use crate::map::TileDescriptor;
+use crate::{
+    atlas::SpriteSheets,
+    map::TileDescriptor,
+    tuning::{PlayerTuning, Tuning, TUNING_PATH},
+};
 
 pub fn setup(app: &mut App) {
-    app.add_editor_window::<TileEditorWindow>();
+    app.add_editor_window::<TileEditorWindow>()
+        .add_editor_window::<TuningWindow>();
 }
 
 struct TileEditorWindow;
@@ -86,7 +89,7 @@ impl EditorWindow for TileEditorWindow {
         let type_registry = world.resource::<AppTypeRegistry>().read();
         // display error
         if let Some(text) = &state.get_error() {
-            ui.colored_label(bevy_editor_pls::egui::Color32::RED, text.to_owned());
+            ui.colored_label(bevy_editor_pls::egui::Color32::RED, text.clone());
         };
         if state.done && state.prop_rules.is_some() {
             state.prop_rules = None;
@@ -233,6 +236,10 @@ impl EditorWindow for TileEditorWindow {
                 ui.checkbox(&mut descriptor.must_be_solid[4], "");
             });
         });
+        ui.horizontal(|ui| {
+            ui.label("Column Part: ");
+            reflect_inspector::ui_for_value(descriptor.column.as_reflect_mut(), ui, &type_registry);
+        });
         ui.horizontal(|ui| {
             ui.label("Variants: ");
             reflect_inspector::ui_for_value(
@@ -241,5 +248,76 @@ impl EditorWindow for TileEditorWindow {
                 &type_registry,
             );
         });
+        // whether the tile collides comes from here, not the descriptor
+        let sheets = world.resource::<SpriteSheets>();
+        for sprite in &descriptor.variants {
+            let name: &'static str = sprite.into();
+            let Some((_, cell)) = sheets.find(*sprite, world.resource(), world.resource()) else {
+                ui.label(format!("{name}: in no sheet"));
+                continue;
+            };
+            let solid = if cell.solid { "solid" } else { "not solid" };
+            ui.label(format!("{name}: {solid} [{}]", cell.tags.join(", ")));
+        }
+    }
+}
+
+/// Changes apply to the running game straight away, saving writes them back to the
+/// tuning file
+struct TuningWindow;
+
+#[derive(Default)]
+struct TuningState {
+    error: Option<String>,
+}
+
+impl EditorWindow for TuningWindow {
+    const NAME: &'static str = "Player Tuning";
+    const DEFAULT_SIZE: (f32, f32) = (100., 100.);
+    type State = TuningState;
+
+    fn ui(
+        world: &mut World,
+        mut cx: bevy_editor_pls::editor_window::EditorWindowContext,
+        ui: &mut bevy_editor_pls::egui::Ui,
+    ) {
+        let Some(state) = cx.state_mut::<Self>() else {
+            error!("State not Loaded");
+            return;
+        };
+        if let Some(text) = &state.error {
+            ui.colored_label(bevy_editor_pls::egui::Color32::RED, text.clone());
+        }
+        let tuning = world.resource::<Tuning>();
+        let handle = tuning.handle.clone();
+        let mut edited = tuning.current.clone();
+        let mut changed = false;
+        for (label, value, range) in [
+            ("Horizontal speed", &mut edited.horizontal_speed, 0. ..=400.),
+            ("Jump speed", &mut edited.jump_speed, 0. ..=600.),
+            ("Speed limit", &mut edited.speed_limit, 0. ..=800.),
+            ("Climb speed", &mut edited.climb_speed, 0. ..=300.),
+            ("Friction", &mut edited.friction, 0. ..=20.),
+            ("Gravity", &mut edited.gravity, 0. ..=1500.),
+        ] {
+            changed |= ui.add(Slider::new(value, range).text(label)).changed();
+        }
+        if changed {
+            // goes through the same path as the file being edited on disk
+            world
+                .resource_mut::<Assets<PlayerTuning>>()
+                .insert(&handle, edited.clone());
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Save").clicked() {
+                state.error = edited.save().err();
+            }
+            if ui.button("Reset").clicked() {
+                world
+                    .resource_mut::<Assets<PlayerTuning>>()
+                    .insert(&handle, PlayerTuning::default());
+            }
+        });
+        ui.label(format!("assets/{TUNING_PATH}"));
     }
 }
@@ -0,0 +1,636 @@
+use avian2d::prelude::*;
+use bevy::{
+    app::AppExit,
+    ecs::{schedule::ScheduleLabel, system::SystemParam},
+    prelude::*,
+};
+
+use crate::{
+    combo::{count_combo, ComboEnded},
+    controls::{rebind_menu_closed, ControlsMenu},
+    destructible::TileBroken,
+    kill_zone::PlayerDied,
+    map::{Gameplay, LoadMap, MapBounds, MapState},
+    player::{Player, PlayerDevice},
+    progression::{Section, Upgrade, UpgradeChoices, UpgradePicked},
+    save::{Profile, Settings},
+    seed::{Seed, SeedSource},
+    FIXED_HZ,
+};
+
+pub fn plugin(app: &mut App) {
+    app.init_state::<GameState>()
+        .init_schedule(StartRun)
+        .init_schedule(StartSection)
+        .init_resource::<RunStats>()
+        .init_resource::<MenuSelection>()
+        .configure_sets(FixedPreUpdate, Gameplay.run_if(in_state(GameState::InGame)))
+        .configure_sets(FixedUpdate, Gameplay.run_if(in_state(GameState::InGame)))
+        .add_systems(StartRun, reset_stats)
+        .add_systems(
+            OnTransition {
+                exited: GameState::Title,
+                entered: GameState::InGame,
+            },
+            start_run,
+        )
+        .add_systems(
+            OnTransition {
+                exited: GameState::Results,
+                entered: GameState::InGame,
+            },
+            start_run,
+        )
+        .add_systems(
+            OnTransition {
+                exited: GameState::Upgrade,
+                entered: GameState::InGame,
+            },
+            start_section,
+        )
+        .add_systems(Startup, sync_physics_time)
+        .add_systems(OnEnter(MapState::Done), sync_physics_time)
+        .add_systems(OnExit(MapState::Done), sync_physics_time)
+        .add_systems(OnEnter(GameState::InGame), sync_physics_time)
+        .add_systems(OnExit(GameState::InGame), sync_physics_time)
+        .add_systems(
+            FixedUpdate,
+            (
+                track_stats,
+                track_combos,
+                track_blocks_broken,
+                track_deaths,
+                game_over,
+            )
+                .chain()
+                .after(count_combo)
+                .in_set(Gameplay),
+        )
+        .add_systems(
+            Update,
+            (
+                pause,
+                navigate_menu,
+                label_menu.run_if(resource_changed::<Profile>),
+                update_menu,
+            )
+                .chain()
+                .run_if(rebind_menu_closed)
+                .before(ControlsMenu),
+        )
+        .add_systems(
+            Update,
+            // each player gets the screen in turn
+            (despawn_screen, spawn_screen)
+                .chain()
+                .run_if(in_state(GameState::Upgrade).and_then(resource_changed::<UpgradeChoices>)),
+        );
+    for state in [
+        GameState::Title,
+        GameState::Settings,
+        GameState::Paused,
+        GameState::Upgrade,
+        GameState::GameOver,
+        GameState::Results,
+    ] {
+        app.add_systems(OnEnter(state), spawn_screen)
+            .add_systems(OnExit(state), despawn_screen);
+    }
+}
+
+/// Where the game is at, gameplay only runs `InGame`
+#[derive(States, Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum GameState {
+    #[default]
+    Title,
+    /// volumes, reached from the title
+    Settings,
+    InGame,
+    /// physics and gameplay are frozen, the world is left as it was
+    Paused,
+    /// a section was cleared and players are picking upgrades
+    Upgrade,
+    /// every player has run out of health
+    GameOver,
+    Results,
+}
+
+/// Runs when a new run starts from the title or results screen, anything that needs
+/// to go back to how it was at the start of a run belongs in here
+#[derive(ScheduleLabel, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct StartRun;
+
+/// Runs when each section of a run starts, including the first
+#[derive(ScheduleLabel, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct StartSection;
+
+fn start_run(world: &mut World) {
+    world.run_schedule(StartRun);
+    world.run_schedule(StartSection);
+}
+
+fn start_section(world: &mut World) {
+    world.run_schedule(StartSection);
+}
+
+/// Add to `Startup` to go straight into a run, it still leaves the title the usual
+/// way so the run starts like any other
+pub fn skip_title(mut next: ResMut<NextState<GameState>>) {
+    next.set(GameState::InGame);
+}
+
+/// Add to `StartRun` to clean up anything left over from the last run
+pub fn despawn_all<C: Component>(mut commands: Commands, entities: Query<Entity, With<C>>) {
+    for entity in &entities {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// How the current run is going, shown on the results screen
+#[derive(Resource, Default, Debug)]
+pub struct RunStats {
+    /// fixed ticks spent in game
+    pub ticks: u32,
+    /// the furthest any player has fallen below the top of the map, in tiles
+    pub depth: u32,
+    /// the longest combo anyone finished
+    pub best_combo: u32,
+    /// picked up or paid out by combos, spending them does not take any off
+    pub coins: u32,
+    /// blocks broken by players
+    pub blocks_broken: u32,
+    /// what knocked the last player out of the run
+    pub death: Option<String>,
+}
+
+impl RunStats {
+    pub fn seconds(&self) -> f64 {
+        self.ticks as f64 / FIXED_HZ
+    }
+}
+
+fn reset_stats(mut stats: ResMut<RunStats>) {
+    *stats = RunStats::default();
+}
+
+fn track_stats(
+    mut stats: ResMut<RunStats>,
+    players: Query<&Transform, With<Player>>,
+    bounds: Query<&MapBounds>,
+) {
+    stats.ticks += 1;
+    let Ok(bounds) = bounds.get_single() else {
+        return;
+    };
+    for transform in &players {
+        let depth = ((bounds.0.max.y - transform.translation.y) / 32.).max(0.) as u32;
+        stats.depth = stats.depth.max(depth);
+    }
+}
+
+fn track_combos(mut stats: ResMut<RunStats>, mut ended: EventReader<ComboEnded>) {
+    for combo in ended.read() {
+        stats.best_combo = stats.best_combo.max(combo.count);
+    }
+}
+
+fn track_blocks_broken(mut stats: ResMut<RunStats>, mut broken: EventReader<TileBroken>) {
+    stats.blocks_broken += broken.read().filter(|event| event.by.is_some()).count() as u32;
+}
+
+fn track_deaths(mut stats: ResMut<RunStats>, mut died: EventReader<PlayerDied>) {
+    if let Some(death) = died.read().last() {
+        stats.death = Some(death.cause.clone());
+    }
+}
+
+fn game_over(players: Query<(), With<Player>>, mut next: ResMut<NextState<GameState>>) {
+    if players.is_empty() {
+        next.set(GameState::GameOver);
+    }
+}
+
+/// Physics only moves while the map is spawned and the game is being played
+fn sync_physics_time(
+    map: Res<State<MapState>>,
+    game: Res<State<GameState>>,
+    mut time: ResMut<Time<Physics>>,
+) {
+    let running = *map == MapState::Done && *game == GameState::InGame;
+    if running && time.is_paused() {
+        time.unpause();
+    } else if !running && !time.is_paused() {
+        time.pause();
+    }
+}
+
+/// Escape or start pauses and unpauses
+fn pause(
+    state: Res<State<GameState>>,
+    mut next: ResMut<NextState<GameState>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    buttons: Res<ButtonInput<GamepadButton>>,
+) {
+    let start = buttons
+        .get_just_pressed()
+        .any(|button| button.button_type == GamepadButtonType::Start);
+    if !keys.just_pressed(KeyCode::Escape) && !start {
+        return;
+    }
+    match state.get() {
+        GameState::InGame => next.set(GameState::Paused),
+        GameState::Paused => next.set(GameState::InGame),
+        _ => {}
+    }
+}
+
+/// One button on a screen, `index` counts down from the top and is what
+/// `MenuSelection` points at
+#[derive(Component)]
+struct MenuButton {
+    index: usize,
+    action: MenuAction,
+}
+
+/// Something a menu button does when picked
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum MenuAction {
+    Play,
+    /// play today's seed
+    Daily,
+    /// play the seed that just ended again
+    Retry,
+    Resume,
+    ShowResults,
+    Title,
+    Quit,
+    Upgrade(Upgrade),
+    Settings,
+    /// picking turns it up a step, back to silent after full, left and right also
+    /// move it while the button is highlighted
+    Volume(Mix),
+}
+
+impl MenuAction {
+    fn label(self, settings: &Settings) -> String {
+        match self {
+            MenuAction::Play => "Play".to_string(),
+            MenuAction::Daily => "Daily run".to_string(),
+            MenuAction::Retry => "Same seed again".to_string(),
+            MenuAction::Resume => "Resume".to_string(),
+            MenuAction::ShowResults => "Continue".to_string(),
+            MenuAction::Title => "Back to title".to_string(),
+            MenuAction::Quit => "Quit".to_string(),
+            MenuAction::Upgrade(upgrade) => upgrade.name().to_string(),
+            MenuAction::Settings => "Settings".to_string(),
+            MenuAction::Volume(mix) => {
+                format!(
+                    "{} {}%",
+                    mix.name(),
+                    mix.steps(settings) * 100 / VOLUME_STEPS
+                )
+            }
+        }
+    }
+}
+
+/// How many steps there are between silent and full volume
+const VOLUME_STEPS: i32 = 10;
+
+/// One of the volumes in `Settings`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Mix {
+    Master,
+    Music,
+    Effects,
+}
+
+impl Mix {
+    fn name(self) -> &'static str {
+        match self {
+            Mix::Master => "Volume",
+            Mix::Music => "Music",
+            Mix::Effects => "Effects",
+        }
+    }
+
+    fn volume(self, settings: &Settings) -> f32 {
+        match self {
+            Mix::Master => settings.volume,
+            Mix::Music => settings.music_volume,
+            Mix::Effects => settings.effects_volume,
+        }
+    }
+
+    fn volume_mut(self, settings: &mut Settings) -> &mut f32 {
+        match self {
+            Mix::Master => &mut settings.volume,
+            Mix::Music => &mut settings.music_volume,
+            Mix::Effects => &mut settings.effects_volume,
+        }
+    }
+
+    fn steps(self, settings: &Settings) -> i32 {
+        (self.volume(settings) * VOLUME_STEPS as f32).round() as i32
+    }
+
+    /// Moves the volume by `by` steps, either wrapping around or stopping at the ends
+    fn nudge(self, settings: &mut Settings, by: i32, wrap: bool) {
+        let steps = self.steps(settings) + by;
+        let steps = if wrap {
+            steps.rem_euclid(VOLUME_STEPS + 1)
+        } else {
+            steps.clamp(0, VOLUME_STEPS)
+        };
+        *self.volume_mut(settings) = steps as f32 / VOLUME_STEPS as f32;
+    }
+}
+
+/// The root of whichever screen is showing
+#[derive(Component)]
+struct Screen;
+
+/// Which button on the current screen is highlighted
+#[derive(Resource, Default)]
+struct MenuSelection(usize);
+
+/// Everything the results screen reports about a run
+#[derive(SystemParam)]
+pub struct RunSummary<'w> {
+    pub stats: Res<'w, RunStats>,
+    pub section: Res<'w, Section>,
+    pub seed: Res<'w, Seed>,
+    pub map: Res<'w, LoadMap>,
+}
+
+fn spawn_screen(
+    mut commands: Commands,
+    state: Res<State<GameState>>,
+    run: RunSummary,
+    choices: Res<UpgradeChoices>,
+    mut selection: ResMut<MenuSelection>,
+    profile: Res<Profile>,
+) {
+    let (title, lines, buttons, background) = match state.get() {
+        GameState::Title => (
+            "WownDell",
+            vec![
+                format!("Today's seed {}", Seed::today()),
+                "F1 for controls".to_string(),
+            ],
+            vec![
+                MenuAction::Play,
+                MenuAction::Daily,
+                MenuAction::Settings,
+                MenuAction::Quit,
+            ],
+            0.5,
+        ),
+        GameState::Settings => (
+            "Settings",
+            vec!["Left and right change a volume".to_string()],
+            vec![
+                MenuAction::Volume(Mix::Master),
+                MenuAction::Volume(Mix::Music),
+                MenuAction::Volume(Mix::Effects),
+                MenuAction::Title,
+            ],
+            0.5,
+        ),
+        GameState::Paused => (
+            "Paused",
+            vec![],
+            vec![
+                MenuAction::Resume,
+                MenuAction::Volume(Mix::Master),
+                MenuAction::Volume(Mix::Music),
+                MenuAction::Volume(Mix::Effects),
+                MenuAction::Title,
+            ],
+            0.6,
+        ),
+        GameState::Upgrade => {
+            let Some((_, index)) = choices.picker else {
+                return;
+            };
+            (
+                "Section Clear",
+                vec![format!("Player {}, pick an upgrade", index + 1)],
+                choices
+                    .offers
+                    .iter()
+                    .map(|upgrade| MenuAction::Upgrade(*upgrade))
+                    .collect(),
+                0.6,
+            )
+        }
+        GameState::GameOver => ("Game Over", vec![], vec![MenuAction::ShowResults], 0.6),
+        GameState::Results => {
+            let seconds = run.stats.seconds();
+            (
+                "Results",
+                vec![
+                    format!("Time {}:{:05.2}", (seconds / 60.) as u32, seconds % 60.),
+                    format!("Section {}", run.section.number),
+                    format!("Depth {}", run.stats.depth),
+                    format!("Best combo {}", run.stats.best_combo),
+                    format!("Coins {}", run.stats.coins),
+                    format!("Seed {}", *run.seed),
+                    format!("Map {}", run.map.0),
+                ],
+                vec![MenuAction::Play, MenuAction::Retry, MenuAction::Title],
+                0.8,
+            )
+        }
+        GameState::InGame => return,
+    };
+    selection.0 = 0;
+    commands
+        .spawn((
+            Name::new(format!("{title} Screen")),
+            Screen,
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(8.),
+                    ..default()
+                },
+                background_color: Color::srgba(0., 0., 0., background).into(),
+                ..default()
+            },
+        ))
+        .with_children(|screen| {
+            screen.spawn(TextBundle::from_section(
+                title,
+                TextStyle {
+                    font_size: 48.,
+                    ..default()
+                },
+            ));
+            for line in lines {
+                screen.spawn(TextBundle::from_section(line, TextStyle::default()));
+            }
+            for (index, action) in buttons.into_iter().enumerate() {
+                screen
+                    .spawn((
+                        ButtonBundle {
+                            style: Style {
+                                width: Val::Percent(60.),
+                                padding: UiRect::all(Val::Px(4.)),
+                                justify_content: JustifyContent::Center,
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        MenuButton { index, action },
+                    ))
+                    .with_children(|button| {
+                        button.spawn(TextBundle::from_section(
+                            action.label(&profile.settings),
+                            TextStyle::default(),
+                        ));
+                    });
+            }
+        });
+}
+
+fn despawn_screen(mut commands: Commands, screens: Query<Entity, With<Screen>>) {
+    for screen in &screens {
+        commands.entity(screen).despawn_recursive();
+    }
+}
+
+/// Up and down move between buttons, enter or south picks one, the mouse also works.
+/// While someone is picking an upgrade only their own device can pick
+fn navigate_menu(
+    mut selection: ResMut<MenuSelection>,
+    mut next: ResMut<NextState<GameState>>,
+    (mut exit, mut picked): (EventWriter<AppExit>, EventWriter<UpgradePicked>),
+    (mut source, mut profile): (ResMut<SeedSource>, ResMut<Profile>),
+    (keys, buttons): (Res<ButtonInput<KeyCode>>, Res<ButtonInput<GamepadButton>>),
+    (choices, devices): (Res<UpgradeChoices>, Query<&PlayerDevice>),
+    menu: Query<(&Interaction, &MenuButton)>,
+) {
+    let count = menu.iter().len();
+    if count == 0 {
+        return;
+    }
+    let pressed = |key: KeyCode, button: GamepadButtonType| {
+        keys.just_pressed(key) || buttons.get_just_pressed().any(|b| b.button_type == button)
+    };
+    if pressed(KeyCode::ArrowUp, GamepadButtonType::DPadUp) {
+        selection.0 = (selection.0 + count - 1) % count;
+    }
+    if pressed(KeyCode::ArrowDown, GamepadButtonType::DPadDown) {
+        selection.0 = (selection.0 + 1) % count;
+    }
+    let by = pressed(KeyCode::ArrowRight, GamepadButtonType::DPadRight) as i32
+        - pressed(KeyCode::ArrowLeft, GamepadButtonType::DPadLeft) as i32;
+    if by != 0 {
+        if let Some(MenuAction::Volume(mix)) = menu
+            .iter()
+            .find(|(_, button)| button.index == selection.0)
+            .map(|(_, button)| button.action)
+        {
+            mix.nudge(&mut profile.settings, by, false);
+        }
+    }
+    let picker = choices
+        .picker
+        .and_then(|(player, _)| devices.get(player).ok());
+    let can_pick = |device: PlayerDevice| {
+        picker.is_none_or(|picker| *picker == device || *picker == PlayerDevice::Any)
+    };
+    let confirm = (keys.just_pressed(KeyCode::Enter) && can_pick(PlayerDevice::Keyboard))
+        || buttons.get_just_pressed().any(|button| {
+            button.button_type == GamepadButtonType::South
+                && can_pick(PlayerDevice::Gamepad(button.gamepad))
+        });
+    let mut chosen = confirm.then_some(selection.0);
+    for (interaction, button) in &menu {
+        match interaction {
+            // the mouse goes with the keyboard
+            Interaction::Pressed if can_pick(PlayerDevice::Keyboard) => chosen = Some(button.index),
+            Interaction::Pressed => {}
+            Interaction::Hovered => selection.0 = button.index,
+            Interaction::None => {}
+        }
+    }
+    let Some((_, button)) =
+        chosen.and_then(|chosen| menu.iter().find(|(_, button)| button.index == chosen))
+    else {
+        return;
+    };
+    match button.action {
+        MenuAction::Play => {
+            source.daily = false;
+            next.set(GameState::InGame);
+        }
+        MenuAction::Daily => {
+            source.daily = true;
+            next.set(GameState::InGame);
+        }
+        MenuAction::Retry => {
+            source.again = true;
+            next.set(GameState::InGame);
+        }
+        MenuAction::Resume => next.set(GameState::InGame),
+        MenuAction::ShowResults => next.set(GameState::Results),
+        MenuAction::Title => next.set(GameState::Title),
+        MenuAction::Quit => {
+            exit.send(AppExit::Success);
+        }
+        MenuAction::Upgrade(upgrade) => {
+            picked.send(UpgradePicked(upgrade));
+        }
+        MenuAction::Settings => next.set(GameState::Settings),
+        MenuAction::Volume(mix) => mix.nudge(&mut profile.settings, 1, true),
+    }
+}
+
+/// Keeps the volume buttons showing the volume they are at
+fn label_menu(
+    profile: Res<Profile>,
+    menu: Query<(&MenuButton, &Children)>,
+    mut texts: Query<&mut Text>,
+) {
+    for (button, children) in &menu {
+        let mut texts = texts.iter_many_mut(children);
+        while let Some(mut text) = texts.fetch_next() {
+            text.sections[0].value = button.action.label(&profile.settings);
+        }
+    }
+}
+
+fn update_menu(
+    selection: Res<MenuSelection>,
+    mut menu: Query<(&MenuButton, &mut BackgroundColor)>,
+) {
+    for (button, mut background) in &mut menu {
+        let color = if button.index == selection.0 {
+            Color::srgb(0.4, 0.2, 0.3)
+        } else {
+            Color::srgb(0.15, 0.15, 0.15)
+        };
+        if background.0 != color {
+            background.0 = color;
+        }
+    }
+}
+
+#[test]
+fn picking_a_volume_wraps_but_nudging_stops() {
+    let mut settings = Settings::default();
+    Mix::Master.nudge(&mut settings, 1, true);
+    assert_eq!(settings.volume, 0.);
+    Mix::Music.nudge(&mut settings, 1, true);
+    assert_eq!(settings.music_volume, 0.8);
+    Mix::Effects.nudge(&mut settings, 1, false);
+    assert_eq!(settings.effects_volume, 1.);
+    Mix::Master.nudge(&mut settings, -1, false);
+    assert_eq!(settings.volume, 0.);
+}
@@ -0,0 +1,408 @@
+use avian2d::prelude::*;
+use bevy::{
+    asset::{AssetLoader, AsyncReadExt, LoadedFolder},
+    prelude::*,
+    utils::HashSet,
+};
+use leafwing_input_manager::prelude::*;
+
+use crate::{
+    audio::Sound,
+    camera::CameraImpulse,
+    destructible::DamageTile,
+    game_state::{despawn_all, StartRun},
+    map::{Gameplay, MapEntities, TileId, TileSprite},
+    particles::{Burst, MUZZLE_FLASH},
+    player::{Climbing, Grounded, Player, PlayerAction},
+};
+
+pub fn plugin(app: &mut App) {
+    app.init_asset::<Gun>()
+        .register_asset_loader(GunLoader)
+        // after the loader so the folder knows about `.gun` files
+        .init_resource::<Guns>()
+        .add_systems(
+            FixedUpdate,
+            (
+                reload,
+                pick_up_guns,
+                cool_down,
+                shoot,
+                fire_bursts,
+                projectile_hits,
+                expire_projectiles,
+            )
+                .chain()
+                .in_set(Gameplay),
+        )
+        .add_systems(StartRun, despawn_all::<Projectile>);
+}
+
+const GUNBOOT_AMMO: u8 = 8;
+/// What gunboots start with, the name of a file in `assets/guns`
+const STARTING_GUN: &str = "single";
+
+/// Jumping while in the air fires downwards instead, refills on landing
+#[derive(Component)]
+pub struct Gunboots {
+    pub ammo: u8,
+    pub max_ammo: u8,
+    /// the gun module fitted, by name
+    pub gun: String,
+}
+
+impl Default for Gunboots {
+    fn default() -> Self {
+        Gunboots {
+            ammo: GUNBOOT_AMMO,
+            max_ammo: GUNBOOT_AMMO,
+            gun: STARTING_GUN.to_string(),
+        }
+    }
+}
+
+/// Kept apart from `Gunboots` so counting down does not look like the ammo changed
+#[derive(Component, Default)]
+pub struct Trigger {
+    /// until the gun can be fired again
+    cooldown: Option<Timer>,
+    /// shots left in the current burst and the time until the next one
+    burst: Option<(u8, Timer)>,
+}
+
+/// How a gun module shoots, loaded from a `.gun` file in `assets/guns` and named
+/// after it. Anything left out of the file is the same as the starting gun
+#[derive(serde::Deserialize, serde::Serialize, Asset, TypePath, Clone, Debug)]
+#[serde(default)]
+pub struct Gun {
+    #[serde(skip)]
+    pub name: String,
+    /// projectiles per shot, fanned out evenly across `spread`
+    pub projectiles: u8,
+    /// degrees between the outermost projectiles
+    pub spread: f32,
+    /// shots fired by one press, `burst_interval` seconds apart
+    pub burst: u8,
+    pub burst_interval: f32,
+    /// seconds from one press until the gun fires again
+    pub cooldown: f32,
+    /// ammo used by one press, however many shots it fires
+    pub ammo_cost: u8,
+    /// upwards speed given to the player by each shot
+    pub recoil: f32,
+    pub damage: u8,
+    pub speed: f32,
+    /// seconds a projectile lasts
+    pub life: f32,
+    pub size: Vec2,
+    /// keeps going through whatever it hits
+    pub pierce: bool,
+    pub sprite: TileSprite,
+}
+
+impl Default for Gun {
+    fn default() -> Self {
+        Gun {
+            name: STARTING_GUN.to_string(),
+            projectiles: 1,
+            spread: 0.,
+            burst: 1,
+            burst_interval: 0.,
+            cooldown: 0.,
+            ammo_cost: 1,
+            recoil: 100.,
+            damage: 1,
+            speed: 600.,
+            life: 1.,
+            size: Vec2::new(8., 16.),
+            pierce: false,
+            sprite: TileSprite::RedDot,
+        }
+    }
+}
+
+/// Keeps every `.gun` file loaded, holding the handle is all this is for
+#[derive(Resource)]
+struct Guns(#[allow(dead_code)] Handle<LoadedFolder>);
+
+impl FromWorld for Guns {
+    fn from_world(world: &mut World) -> Self {
+        Guns(world.resource::<AssetServer>().load_folder("guns"))
+    }
+}
+
+/// Looked up every shot so edits to a `.gun` file apply straight away, falls back
+/// to the starting gun while the files are still loading
+fn find_gun(guns: &Assets<Gun>, name: &str) -> Gun {
+    guns.iter()
+        .map(|(_, gun)| gun)
+        .find(|gun| gun.name == name)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Touching this swaps the player's gun module for `0`
+#[derive(Component)]
+pub struct GunPickup(pub String);
+
+#[derive(Component)]
+pub struct Projectile {
+    damage: u8,
+    life: Timer,
+    /// the player who fired it
+    owner: Entity,
+    pierce: bool,
+}
+
+/// Landing also cuts off whatever is left of a burst
+fn reload(mut players: Query<(&mut Gunboots, &mut Trigger), With<Grounded>>) {
+    for (mut gunboots, mut trigger) in &mut players {
+        if gunboots.ammo != gunboots.max_ammo {
+            gunboots.ammo = gunboots.max_ammo;
+        }
+        trigger.burst = None;
+    }
+}
+
+fn pick_up_guns(
+    mut commands: Commands,
+    mut players: Query<(&CollidingEntities, &mut Gunboots, &mut Trigger), With<Player>>,
+    pickups: Query<(&GunPickup, &TileId)>,
+    mut maps: Query<&mut MapEntities>,
+) {
+    for (touching, mut gunboots, mut trigger) in &mut players {
+        for &entity in touching.iter() {
+            let Ok((pickup, id)) = pickups.get(entity) else {
+                continue;
+            };
+            gunboots.gun = pickup.0.clone();
+            *trigger = Trigger::default();
+            for mut map in &mut maps {
+                map.remove(id.0);
+            }
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+type Shooter = (
+    Entity,
+    &'static ActionState<PlayerAction>,
+    &'static Transform,
+    &'static mut LinearVelocity,
+    &'static mut Gunboots,
+    &'static mut Trigger,
+);
+
+/// players that are in the air and not holding on to anything
+type Airborne = (With<Player>, Without<Grounded>, Without<Climbing>);
+
+/// Cooldowns keep counting down on the ground, only firing needs the player in the air
+fn cool_down(mut triggers: Query<&mut Trigger>, time: Res<Time>) {
+    for mut trigger in &mut triggers {
+        if let Some(cooldown) = &mut trigger.cooldown {
+            if cooldown.tick(time.delta()).finished() {
+                trigger.cooldown = None;
+            }
+        }
+    }
+}
+
+fn shoot(
+    mut commands: Commands,
+    mut players: Query<Shooter, Airborne>,
+    guns: Res<Assets<Gun>>,
+    mut camera: EventWriter<CameraImpulse>,
+    mut sounds: EventWriter<Sound>,
+    mut bursts: EventWriter<Burst>,
+) {
+    for (player, input, transform, mut velocity, mut gunboots, mut trigger) in &mut players {
+        if !input.just_pressed(&PlayerAction::Jump) || trigger.cooldown.is_some() {
+            continue;
+        }
+        let gun = find_gun(&guns, &gunboots.gun);
+        if gunboots.ammo < gun.ammo_cost {
+            continue;
+        }
+        gunboots.ammo -= gun.ammo_cost;
+        if gun.cooldown > 0. {
+            trigger.cooldown = Some(Timer::from_seconds(gun.cooldown, TimerMode::Once));
+        }
+        if gun.burst > 1 {
+            trigger.burst = Some((
+                gun.burst - 1,
+                Timer::from_seconds(gun.burst_interval, TimerMode::Repeating),
+            ));
+        }
+        fire(&mut commands, &gun, player, transform, &mut velocity);
+        camera.send(CameraImpulse::Shake(0.15));
+        sounds.send(Sound::Shoot);
+        bursts.send(muzzle_flash(transform));
+    }
+}
+
+/// The rest of a burst keeps firing as long as the player stays in the air
+fn fire_bursts(
+    mut commands: Commands,
+    mut players: Query<Shooter, Airborne>,
+    guns: Res<Assets<Gun>>,
+    mut sounds: EventWriter<Sound>,
+    mut bursts: EventWriter<Burst>,
+    time: Res<Time>,
+) {
+    for (player, _, transform, mut velocity, gunboots, mut trigger) in &mut players {
+        let Some((left, timer)) = &mut trigger.burst else {
+            continue;
+        };
+        if !timer.tick(time.delta()).just_finished() {
+            continue;
+        }
+        *left -= 1;
+        if *left == 0 {
+            trigger.burst = None;
+        }
+        fire(
+            &mut commands,
+            &find_gun(&guns, &gunboots.gun),
+            player,
+            transform,
+            &mut velocity,
+        );
+        sounds.send(Sound::Shoot);
+        bursts.send(muzzle_flash(transform));
+    }
+}
+
+fn muzzle_flash(transform: &Transform) -> Burst {
+    Burst {
+        emitter: MUZZLE_FLASH,
+        at: transform.translation.truncate() - Vec2::Y * 16.,
+    }
+}
+
+fn fire(
+    commands: &mut Commands,
+    gun: &Gun,
+    player: Entity,
+    transform: &Transform,
+    velocity: &mut LinearVelocity,
+) {
+    velocity.y = velocity.y.max(gun.recoil);
+    let count = gun.projectiles.max(1);
+    for i in 0..count {
+        let angle = if count == 1 {
+            0.
+        } else {
+            (i as f32 / (count - 1) as f32 - 0.5) * gun.spread.to_radians()
+        };
+        let direction = Vec2::from_angle(angle).rotate(Vec2::NEG_Y);
+        commands.spawn((
+            Name::new("Projectile"),
+            Projectile {
+                damage: gun.damage,
+                life: Timer::from_seconds(gun.life, TimerMode::Once),
+                owner: player,
+                pierce: gun.pierce,
+            },
+            SpriteBundle {
+                transform: Transform::from_translation(transform.translation - Vec3::Y * 24.)
+                    .with_rotation(Quat::from_rotation_z(angle)),
+                sprite: Sprite {
+                    custom_size: Some(gun.size.max(Vec2::splat(16.))),
+                    ..default()
+                },
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            TextureAtlas::default(),
+            gun.sprite,
+            RigidBody::Kinematic,
+            LinearVelocity(direction * gun.speed),
+            Collider::rectangle(gun.size.x, gun.size.y),
+            Sensor,
+        ));
+    }
+}
+
+fn projectile_hits(
+    mut commands: Commands,
+    mut collisions: EventReader<CollisionStarted>,
+    projectiles: Query<&Projectile>,
+    players: Query<(), With<Player>>,
+    sensors: Query<(), With<Sensor>>,
+    mut damage: EventWriter<DamageTile>,
+) {
+    let mut spent = HashSet::new();
+    for CollisionStarted(a, b) in collisions.read() {
+        for (projectile, other) in [(*a, *b), (*b, *a)] {
+            let Ok(stats) = projectiles.get(projectile) else {
+                continue;
+            };
+            if players.contains(other) || sensors.contains(other) || spent.contains(&projectile) {
+                continue;
+            }
+            damage.send(DamageTile {
+                tile: other,
+                damage: stats.damage,
+                by: Some(stats.owner),
+            });
+            if !stats.pierce {
+                spent.insert(projectile);
+                commands.entity(projectile).despawn_recursive();
+            }
+        }
+    }
+}
+
+fn expire_projectiles(
+    mut commands: Commands,
+    mut projectiles: Query<(Entity, &mut Projectile)>,
+    time: Res<Time>,
+) {
+    for (entity, mut projectile) in &mut projectiles {
+        if projectile.life.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+struct GunLoader;
+
+impl AssetLoader for GunLoader {
+    type Asset = Gun;
+    type Settings = ();
+    type Error = &'static str;
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut bevy::asset::io::Reader,
+        _settings: &'a Self::Settings,
+        load_context: &'a mut bevy::asset::LoadContext,
+    ) -> impl bevy::utils::ConditionalSendFuture<Output = Result<Self::Asset, Self::Error>> {
+        load_gun(reader, load_context)
+    }
+    fn extensions(&self) -> &[&str] {
+        &["gun"]
+    }
+}
+
+async fn load_gun(
+    reader: &mut bevy::asset::io::Reader<'_>,
+    load_context: &mut bevy::asset::LoadContext<'_>,
+) -> Result<Gun, &'static str> {
+    let mut data = String::new();
+    if reader.read_to_string(&mut data).await.is_err() {
+        return Err("Failed to read to string");
+    }
+    let mut gun: Gun = match ron::from_str(&data) {
+        Ok(data) => data,
+        Err(e) => {
+            error!("{e}");
+            return Err("Ron Failed");
+        }
+    };
+    let Some(name) = load_context.path().file_stem() else {
+        return Err("Gun file has no name");
+    };
+    gun.name = name.to_string_lossy().into_owned();
+    Ok(gun)
+}
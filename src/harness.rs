@@ -0,0 +1,504 @@
+//! Runs the game without a window or GPU so gameplay can be tested on any machine
+
+use std::{path::Path, sync::Arc, time::Duration};
+
+use avian2d::prelude::*;
+use bevy::{
+    asset::io::{
+        memory::{Dir, MemoryAssetReader},
+        AssetSource,
+    },
+    input::InputPlugin,
+    prelude::*,
+    scene::ScenePlugin,
+    state::app::StatesPlugin,
+    time::TimeUpdateStrategy,
+};
+use rand::Rng;
+
+use crate::{
+    audio::{Sound, Sounds, Voice},
+    combo::Combo,
+    destructible::{Coin, DamageTile, Destructible},
+    game_state::{skip_title, GameState, RunStats},
+    gunboots::{Gun, GunPickup, Gunboots, Projectile},
+    leaderboard::Leaderboard,
+    map::{LoadMap, MapState, Tile, TileSprite, Tiles},
+    particles::Particle,
+    player::{Grounded, Health, Player, PlayerAction, PlayerStats, Wallet},
+    progression::{Section, Upgrade, UpgradePicked},
+    replay::{Replay, ReplayMode},
+    save::{Profile, SaveDir},
+    seed::{GameRng, Seed, SeedSource},
+    tuning::{PlayerTuning, Tuning},
+    FIXED_HZ,
+};
+
+/// How many frames to wait for assets before giving up
+const LOAD_FRAMES: usize = 1000;
+
+/// Every test run plays out with the same seed
+const SEED: Seed = Seed(0x5EED);
+
+pub struct Sim {
+    pub app: App,
+}
+
+impl Sim {
+    /// Load a map from `assets/`
+    pub fn from_file(path: &str) -> Sim {
+        Sim::new(LoadMap(path.to_string()), |_| {})
+    }
+
+    /// Load a map written in the same format as a `.map` file
+    pub fn from_str(map: &str) -> Sim {
+        let dir = Dir::default();
+        dir.insert_asset_text(Path::new("test.map"), map);
+        Sim::new(LoadMap("memory://test.map".to_string()), move |app| {
+            let dir = dir.clone();
+            app.register_asset_source(
+                "memory",
+                AssetSource::build()
+                    .with_reader(move || Box::new(MemoryAssetReader { root: dir.clone() })),
+            );
+        })
+    }
+
+    fn new(map: LoadMap, sources: impl FnOnce(&mut App)) -> Sim {
+        let mut app = App::new();
+        sources(&mut app);
+        app.add_plugins((
+            MinimalPlugins,
+            AssetPlugin {
+                watch_for_changes_override: Some(false),
+                ..default()
+            },
+            InputPlugin,
+            TransformPlugin,
+            HierarchyPlugin,
+            StatesPlugin,
+            // avian looks for scenes to build colliders from
+            ScenePlugin,
+        ))
+        .init_asset::<Image>()
+        .init_asset::<TextureAtlasLayout>()
+        // audio never plays, but sounds still look for their clips
+        .init_asset::<AudioSource>()
+        .insert_resource(map)
+        .insert_resource(SeedSource {
+            fixed: Some(SEED),
+            ..default()
+        })
+        // tests never touch the real profile
+        .insert_resource(SaveDir(None))
+        // one fixed tick every update
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+            1. / FIXED_HZ,
+        )))
+        .add_plugins(crate::game)
+        .add_systems(Startup, skip_title);
+        // freeze frames would stop ticks from lining up with updates
+        app.world_mut()
+            .resource_mut::<Profile>()
+            .settings
+            .freeze_frames = false;
+
+        let mut sim = Sim { app };
+        sim.wait_until_loaded();
+        sim
+    }
+
+    /// Update until the map has spawned and every tile has been given a sprite
+    fn wait_until_loaded(&mut self) {
+        self.wait_for(|world| {
+            let tiles = world.resource::<Tiles>().0.id();
+            world
+                .resource::<AssetServer>()
+                .is_loaded_with_dependencies(tiles)
+                && *world.resource::<State<MapState>>() == MapState::Done
+        });
+        // descriptors that finished loading this frame reach their tiles next frame
+        self.app.update();
+    }
+
+    /// Update until `ready` says so, giving assets loading in the background time to finish
+    pub fn wait_for(&mut self, ready: impl Fn(&World) -> bool) {
+        for _ in 0..LOAD_FRAMES {
+            self.app.update();
+            if ready(self.app.world()) {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        panic!("still not ready after {LOAD_FRAMES} frames");
+    }
+
+    /// Hold `actions` for player one for `ticks` fixed ticks, or for as long as they
+    /// are still alive since playback brings back anyone it has input for
+    pub fn run(&mut self, ticks: usize, actions: &[PlayerAction]) {
+        for _ in 0..ticks {
+            let held = if self.alive() {
+                vec![(0, actions.to_vec())]
+            } else {
+                vec![]
+            };
+            let replay = Replay {
+                ticks: vec![held],
+                ..default()
+            };
+            self.app
+                .insert_resource(ReplayMode::Playback { replay, tick: 0 });
+            self.app.update();
+        }
+    }
+
+    fn alive(&mut self) -> bool {
+        let mut players = self.app.world_mut().query::<&Player>();
+        players.iter(self.app.world()).any(|player| player.0 == 0)
+    }
+
+    /// Like `run` but calls `each` after every tick
+    pub fn run_with(
+        &mut self,
+        ticks: usize,
+        actions: &[PlayerAction],
+        mut each: impl FnMut(&mut Sim),
+    ) {
+        for _ in 0..ticks {
+            self.run(1, actions);
+            each(self);
+        }
+    }
+
+    pub fn player(&mut self) -> Vec2 {
+        let mut players = self.app.world_mut().query::<(&Player, &Position)>();
+        players
+            .iter(self.app.world())
+            .find(|(player, _)| player.0 == 0)
+            .map(|(_, position)| position.0)
+            .expect("player one to exist")
+    }
+
+    pub fn grounded(&mut self) -> bool {
+        let mut players = self
+            .app
+            .world_mut()
+            .query_filtered::<&Player, With<Grounded>>();
+        players.iter(self.app.world()).any(|player| player.0 == 0)
+    }
+
+    pub fn wallet(&mut self) -> u32 {
+        let mut players = self.app.world_mut().query::<(&Player, &Wallet)>();
+        players
+            .iter(self.app.world())
+            .find(|(player, _)| player.0 == 0)
+            .map_or(0, |(_, wallet)| wallet.0)
+    }
+
+    pub fn health(&mut self) -> u8 {
+        let mut players = self.app.world_mut().query::<(&Player, &Health)>();
+        players
+            .iter(self.app.world())
+            .find(|(player, _)| player.0 == 0)
+            .map_or(0, |(_, health)| health.current)
+    }
+
+    pub fn max_health(&mut self) -> u8 {
+        let mut players = self.app.world_mut().query::<(&Player, &Health)>();
+        players
+            .iter(self.app.world())
+            .find(|(player, _)| player.0 == 0)
+            .map(|(_, health)| health.max)
+            .expect("player one to exist")
+    }
+
+    pub fn state(&self) -> GameState {
+        *self.app.world().resource::<State<GameState>>().get()
+    }
+
+    pub fn count<C: Component>(&mut self) -> usize {
+        let mut query = self.app.world_mut().query_filtered::<(), With<C>>();
+        query.iter(self.app.world()).count()
+    }
+}
+
+/// A floor from x = `-half` to `half` at y = 0
+fn floor(half: i32) -> String {
+    (-half..=half)
+        .map(|x| format!("Dirt X {x} Y 0\n"))
+        .collect()
+}
+
+/// Standing on the floor is 1 tile up, the player is a tile tall
+const STANDING: f32 = 32.;
+
+#[test]
+fn lands_on_dirt() {
+    let mut sim = Sim::from_str(&floor(3));
+    sim.run(180, &[]);
+    assert!(sim.grounded());
+    assert!((sim.player().y - STANDING).abs() < 1., "{}", sim.player());
+}
+
+#[test]
+fn jump_height() {
+    let mut sim = Sim::from_str(&floor(3));
+    sim.run(180, &[]);
+    sim.run(1, &[PlayerAction::Jump]);
+    let mut top = f32::MIN;
+    sim.run_with(120, &[], |sim| top = top.max(sim.player().y));
+    // v^2 / 2g with a jump speed of 250 and gravity of 500
+    let height = top - STANDING;
+    assert!((50. ..70.).contains(&height), "jumped {height}");
+    assert!(sim.grounded());
+}
+
+#[test]
+fn well_walls_stop_the_player() {
+    let mut sim = Sim::from_str(&floor(10));
+    sim.run(120, &[]);
+    sim.run(300, &[PlayerAction::MoveRight]);
+    let x = sim.player().x;
+    assert!(x > 200. && x < 224.5, "walked to {x}");
+}
+
+#[test]
+fn stomping_breaks_blocks_into_coins() {
+    let mut sim = Sim::from_str("Breakable X 0 Y 0 coins 2\nDirt X 0 Y -3");
+    assert_eq!(sim.count::<Destructible>(), 1);
+    sim.run(180, &[]);
+    assert_eq!(sim.count::<Destructible>(), 0);
+    // the coins fall back down on the player so some may already be picked up
+    assert_eq!(sim.count::<Coin>() as u32 + sim.wallet(), 2);
+}
+
+#[test]
+fn broken_blocks_leave_debris_that_fades() {
+    let mut sim = Sim::from_str("Breakable X 0 Y 0\nDirt X 0 Y -3");
+    for _ in 0..180 {
+        sim.run(1, &[]);
+        if sim.count::<Destructible>() == 0 {
+            break;
+        }
+    }
+    assert_eq!(sim.count::<Destructible>(), 0);
+    assert!(sim.count::<Particle>() > 0);
+    sim.run(120, &[]);
+    assert_eq!(sim.count::<Particle>(), 0);
+}
+
+#[test]
+fn dirt_is_autotiled() {
+    let mut sim = Sim::from_str(&floor(3));
+    let mut tiles = sim.app.world_mut().query::<(&Tile, &TileSprite)>();
+    let sprites: Vec<_> = tiles
+        .iter(sim.app.world())
+        .filter(|(tile, _)| **tile == Tile::Dirt)
+        .map(|(_, sprite)| *sprite)
+        .collect();
+    assert_eq!(sprites.len(), 7);
+    assert!(sprites.iter().all(|sprite| *sprite != TileSprite::Air));
+}
+
+#[test]
+fn lobby_loads() {
+    let mut sim = Sim::from_file("maps/lobby.map");
+    assert!(sim.count::<Tile>() > 0);
+    sim.run(60, &[]);
+    assert!(sim.player().y < 320.);
+}
+
+#[test]
+fn skipping_the_title_starts_a_run() {
+    let sim = Sim::from_str(&floor(3));
+    let world = sim.app.world();
+    assert_eq!(world.resource::<Section>().number, 1);
+    assert_eq!(*world.resource::<Seed>(), SEED);
+    let mut rng = world.resource::<GameRng>().0.clone();
+    assert_eq!(rng.gen::<u64>(), GameRng::new(SEED).0.gen::<u64>());
+}
+
+#[test]
+fn falling_into_the_void_ends_the_run() {
+    let mut sim = Sim::from_str("Void X -1 Y 0\nVoid X 0 Y 0\nVoid X 1 Y 0");
+    sim.run(600, &[]);
+    assert_eq!(sim.count::<Player>(), 0);
+    assert_eq!(sim.state(), GameState::GameOver);
+    let profile = sim.app.world().resource::<Profile>();
+    assert_eq!(profile.runs, 1);
+    assert_eq!(profile.deaths.get("Void"), Some(&1));
+    let leaderboard = sim.app.world().resource::<Leaderboard>();
+    assert_eq!(leaderboard.runs.len(), 1);
+    assert_eq!(leaderboard.runs[0].death, "Void");
+}
+
+#[test]
+fn landing_across_two_voids_only_hurts_once() {
+    let mut sim = Sim::from_str("Void X 0 Y 0\nVoid X 1 Y 0");
+    sim.run(1, &[]);
+    let mut players = sim
+        .app
+        .world_mut()
+        .query_filtered::<&mut Position, With<Player>>();
+    players.single_mut(sim.app.world_mut()).x = 16.;
+    let full = sim.health();
+    for _ in 0..300 {
+        sim.run(1, &[]);
+        if sim.health() < full {
+            break;
+        }
+    }
+    assert_eq!(sim.health(), full - 1);
+}
+
+#[test]
+fn stomping_down_a_column_is_a_combo() {
+    let mut map: String = (0..5)
+        .map(|i| format!("Breakable X 0 Y {}\n", i * -3))
+        .collect();
+    map.push_str("Dirt X 0 Y -15");
+    let mut sim = Sim::from_str(&map);
+    sim.run(300, &[]);
+    assert_eq!(sim.count::<Destructible>(), 0);
+    assert_eq!(sim.app.world().resource::<RunStats>().best_combo, 5);
+    // the first reward is at 5
+    assert_eq!(sim.wallet(), 5);
+}
+
+#[test]
+fn blocks_broken_on_the_ground_are_not_a_combo() {
+    let mut sim = Sim::from_str(&format!("Breakable X 3 Y 3\n{}", floor(3)));
+    sim.run(180, &[]);
+    assert!(sim.grounded());
+    let mut blocks = sim
+        .app
+        .world_mut()
+        .query_filtered::<Entity, With<Destructible>>();
+    let block = blocks.single(sim.app.world());
+    let mut players = sim.app.world_mut().query_filtered::<Entity, With<Player>>();
+    let player = players.single(sim.app.world());
+    sim.app.world_mut().send_event(DamageTile {
+        tile: block,
+        damage: u8::MAX,
+        by: Some(player),
+    });
+    sim.run(2, &[]);
+    assert_eq!(sim.count::<Destructible>(), 0);
+    let mut combos = sim.app.world_mut().query::<&Combo>();
+    assert_eq!(combos.single(sim.app.world()).0, 0);
+}
+
+#[test]
+fn dying_ends_the_combo() {
+    let mut map: String = (0..5)
+        .map(|i| format!("Breakable X 0 Y {}\n", i * -3))
+        .collect();
+    map.push_str("Void X -1 Y -15\nVoid X 0 Y -15\nVoid X 1 Y -15");
+    let mut sim = Sim::from_str(&map);
+    sim.run(300, &[]);
+    assert_eq!(sim.count::<Destructible>(), 0);
+    let stats = sim.app.world().resource::<RunStats>();
+    assert_eq!(stats.best_combo, 5);
+    assert_eq!(stats.coins, 5);
+}
+
+#[test]
+fn falling_out_the_bottom_clears_the_section() {
+    let mut sim = Sim::from_str("Dirt X -3 Y 0\nDirt X 3 Y 0");
+    sim.run(300, &[]);
+    assert_eq!(sim.state(), GameState::Upgrade);
+    sim.app
+        .world_mut()
+        .send_event(UpgradePicked(Upgrade::ExtraHealth));
+    sim.app.update();
+    sim.app.update();
+    assert_eq!(sim.state(), GameState::InGame);
+    assert_eq!(sim.app.world().resource::<Section>().number, 2);
+    assert_eq!(sim.max_health(), 5);
+}
+
+#[test]
+fn buying_from_the_shop() {
+    let mut sim = Sim::from_str("Dirt X -1 Y 0\nDirt X 0 Y 0 sells extra_health 3\nDirt X 1 Y 0");
+    sim.run(120, &[]);
+    let mut wallets = sim.app.world_mut().query::<&mut Wallet>();
+    wallets.single_mut(sim.app.world_mut()).0 = 5;
+    sim.run(1, &[PlayerAction::Down]);
+    sim.run(1, &[]);
+    assert_eq!(sim.wallet(), 2);
+    assert_eq!(sim.max_health(), 5);
+    // it only sells once
+    sim.run(1, &[PlayerAction::Down]);
+    assert_eq!(sim.wallet(), 2);
+}
+
+#[test]
+fn picking_up_a_triple_spread() {
+    let mut sim = Sim::from_str(&format!("Collectable X 0 Y 3 gun triple\n{}", floor(3)));
+    sim.run(180, &[]);
+    assert_eq!(sim.count::<GunPickup>(), 0);
+    let mut gunboots = sim.app.world_mut().query::<&Gunboots>();
+    assert_eq!(gunboots.single(sim.app.world()).gun, "triple");
+
+    sim.wait_for(|world| {
+        let guns = world.resource::<Assets<Gun>>();
+        guns.iter().any(|(_, gun)| gun.name == "triple")
+    });
+    sim.run(1, &[PlayerAction::Jump]);
+    sim.run(10, &[]);
+    sim.run(1, &[PlayerAction::Jump]);
+    assert_eq!(sim.count::<Projectile>(), 3);
+}
+
+#[test]
+fn retuning_keeps_upgrades() {
+    let mut sim = Sim::from_str(&floor(3));
+    let handle = sim.app.world().resource::<Tuning>().handle.clone();
+    sim.wait_for(|world| world.resource::<Assets<PlayerTuning>>().contains(&handle));
+    sim.run(1, &[]);
+    let mut stats = sim.app.world_mut().query::<&mut PlayerStats>();
+    stats.single_mut(sim.app.world_mut()).jump_speed += 30.;
+
+    let tuning = PlayerTuning {
+        jump_speed: 300.,
+        gravity: 800.,
+        ..default()
+    };
+    sim.app
+        .world_mut()
+        .resource_mut::<Assets<PlayerTuning>>()
+        .insert(&handle, tuning);
+    // asset events go out at the end of the frame
+    sim.run(2, &[]);
+    assert_eq!(stats.single(sim.app.world()).jump_speed, 330.);
+    assert_eq!(sim.app.world().resource::<Gravity>().0.y, -800.);
+}
+
+#[test]
+fn sounds_past_their_voice_limit_are_dropped() {
+    let mut sim = Sim::from_str(&floor(3));
+    sim.wait_for(|world| !world.resource::<Sounds>().effects.is_empty());
+    let sounds = sim.app.world().resource::<Sounds>();
+    let voices = sounds.bank.effects[&Sound::Coin].voices;
+    let clips = sounds.effects[&Sound::Coin].clone();
+    // there is no audio device to play them, so every clip stays playing
+    for clip in &clips {
+        sim.app
+            .world_mut()
+            .resource_mut::<Assets<AudioSource>>()
+            .insert(
+                clip,
+                AudioSource {
+                    bytes: Arc::new([]),
+                },
+            );
+    }
+    for _ in 0..voices * 3 {
+        sim.app.world_mut().send_event(Sound::Coin);
+    }
+    sim.app.update();
+    assert_eq!(sim.count::<Voice>(), voices);
+    sim.app.world_mut().send_event(Sound::Coin);
+    sim.app.update();
+    assert_eq!(sim.count::<Voice>(), voices);
+}
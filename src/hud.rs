@@ -0,0 +1,370 @@
+use bevy::prelude::*;
+
+use crate::{
+    combo::{Combo, ComboEnded},
+    game_state::GameState,
+    gunboots::Gunboots,
+    player::{Health, Player, Wallet},
+};
+
+pub fn plugin(app: &mut App) {
+    app.add_systems(Startup, spawn_hud)
+        .add_systems(Update, show_hud.run_if(state_changed::<GameState>))
+        .add_systems(
+            PostUpdate,
+            (
+                (add_panels, remove_panels),
+                (
+                    update_health,
+                    update_ammo,
+                    update_wallet,
+                    (update_combo, show_combo_result, clear_combo_result).chain(),
+                ),
+            )
+                .chain(),
+        );
+}
+
+const PANEL_GAP: f32 = 8.;
+const AMMO_BAR_HEIGHT: f32 = 96.;
+const PIP_SIZE: f32 = 12.;
+const EMPTY: Color = Color::srgb(0.15, 0.15, 0.15);
+const HEALTH_COLOR: Color = Color::srgb(0.9, 0.2, 0.2);
+const AMMO_COLOR: Color = Color::srgb(1., 0.85, 0.3);
+
+/// Holds one panel per player along the top of the window
+#[derive(Component)]
+struct Hud;
+
+/// Every part of the HUD that shows a player's stats points back at that player
+#[derive(Component)]
+struct HudPanel(Entity);
+
+#[derive(Component)]
+struct HealthPip {
+    player: Entity,
+    index: u8,
+}
+
+#[derive(Component)]
+struct AmmoFill(Entity);
+
+#[derive(Component)]
+struct WalletText(Entity);
+
+/// How long what a combo paid out stays up after it ends
+const COMBO_RESULT_SECONDS: f32 = 2.;
+
+#[derive(Component)]
+struct ComboText {
+    player: Entity,
+    /// counting down to clearing the result of the last combo
+    result: Option<Timer>,
+}
+
+fn spawn_hud(mut commands: Commands) {
+    commands.spawn((
+        Name::new("HUD"),
+        Hud,
+        NodeBundle {
+            style: Style {
+                width: Val::Percent(100.),
+                padding: UiRect::all(Val::Px(PANEL_GAP)),
+                column_gap: Val::Px(PANEL_GAP),
+                justify_content: JustifyContent::SpaceBetween,
+                align_items: AlignItems::FlexStart,
+                ..default()
+            },
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+    ));
+}
+
+/// Only shown while a run is on screen
+fn show_hud(state: Res<State<GameState>>, mut hud: Query<&mut Visibility, With<Hud>>) {
+    let visible = matches!(
+        state.get(),
+        GameState::InGame | GameState::Paused | GameState::Upgrade
+    );
+    for mut visibility in &mut hud {
+        *visibility = if visible {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+type NewPlayer = (
+    Entity,
+    &'static Player,
+    &'static Sprite,
+    &'static Health,
+    &'static Gunboots,
+    &'static Wallet,
+);
+
+/// Panels are built with the player's current stats since the update systems only
+/// hear about changes
+fn add_panels(
+    mut commands: Commands,
+    players: Query<NewPlayer, Added<Player>>,
+    hud: Query<Entity, With<Hud>>,
+) {
+    let Ok(hud) = hud.get_single() else {
+        return;
+    };
+    for (player, index, sprite, health, gunboots, wallet) in &players {
+        let panel = commands
+            .spawn((
+                Name::new(format!("Player {} HUD", index.0 + 1)),
+                HudPanel(player),
+                NodeBundle {
+                    style: Style {
+                        column_gap: Val::Px(4.),
+                        ..default()
+                    },
+                    ..default()
+                },
+            ))
+            .with_children(|panel| {
+                // ammo empties downwards, like the boots it comes out of
+                panel
+                    .spawn(NodeBundle {
+                        style: Style {
+                            width: Val::Px(PIP_SIZE),
+                            height: Val::Px(AMMO_BAR_HEIGHT),
+                            flex_direction: FlexDirection::ColumnReverse,
+                            border: UiRect::all(Val::Px(2.)),
+                            ..default()
+                        },
+                        background_color: EMPTY.into(),
+                        border_color: Color::BLACK.into(),
+                        ..default()
+                    })
+                    .with_children(|bar| {
+                        bar.spawn((
+                            AmmoFill(player),
+                            NodeBundle {
+                                style: Style {
+                                    width: Val::Percent(100.),
+                                    height: ammo_height(gunboots),
+                                    ..default()
+                                },
+                                background_color: AMMO_COLOR.into(),
+                                ..default()
+                            },
+                        ));
+                    });
+                panel
+                    .spawn(NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Column,
+                            row_gap: Val::Px(4.),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|stats| {
+                        stats.spawn(TextBundle::from_section(
+                            format!("P{}", index.0 + 1),
+                            TextStyle {
+                                color: sprite.color,
+                                ..default()
+                            },
+                        ));
+                        stats
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    column_gap: Val::Px(2.),
+                                    ..default()
+                                },
+                                ..default()
+                            })
+                            .with_children(|pips| {
+                                for index in 0..health.max {
+                                    pips.spawn(health_pip(player, health, index));
+                                }
+                            });
+                        stats.spawn((
+                            WalletText(player),
+                            TextBundle::from_section(
+                                wallet_text(wallet),
+                                TextStyle {
+                                    color: AMMO_COLOR,
+                                    ..default()
+                                },
+                            ),
+                        ));
+                        stats.spawn((
+                            ComboText {
+                                player,
+                                result: None,
+                            },
+                            TextBundle::from_section("", TextStyle::default()),
+                        ));
+                    });
+            })
+            .id();
+        commands.entity(hud).add_child(panel);
+    }
+}
+
+fn remove_panels(
+    mut commands: Commands,
+    mut removed: RemovedComponents<Player>,
+    panels: Query<(Entity, &HudPanel)>,
+) {
+    for player in removed.read() {
+        for (panel, owner) in &panels {
+            if owner.0 == player {
+                commands.entity(panel).despawn_recursive();
+            }
+        }
+    }
+}
+
+fn ammo_height(gunboots: &Gunboots) -> Val {
+    Val::Percent(100. * gunboots.ammo as f32 / gunboots.max_ammo.max(1) as f32)
+}
+
+fn pip_color(health: &Health, index: u8) -> Color {
+    if index < health.current {
+        HEALTH_COLOR
+    } else {
+        EMPTY
+    }
+}
+
+fn wallet_text(wallet: &Wallet) -> String {
+    format!("{} coins", wallet.0)
+}
+
+fn health_pip(player: Entity, health: &Health, index: u8) -> impl Bundle {
+    (
+        HealthPip { player, index },
+        NodeBundle {
+            style: Style {
+                width: Val::Px(PIP_SIZE),
+                height: Val::Px(PIP_SIZE),
+                ..default()
+            },
+            background_color: pip_color(health, index).into(),
+            ..default()
+        },
+    )
+}
+
+/// Pips are added when max health goes up, and taken away if it ever goes down
+fn update_health(
+    mut commands: Commands,
+    players: Query<(Entity, &Health), Changed<Health>>,
+    mut pips: Query<(Entity, &HealthPip, &Parent, &mut BackgroundColor)>,
+) {
+    for (player, health) in &players {
+        let mut row = None;
+        let mut shown = 0;
+        for (entity, pip, parent, mut color) in &mut pips {
+            if pip.player != player {
+                continue;
+            }
+            row = Some(parent.get());
+            if pip.index >= health.max {
+                commands.entity(entity).despawn_recursive();
+                continue;
+            }
+            color.0 = pip_color(health, pip.index);
+            shown = shown.max(pip.index + 1);
+        }
+        let Some(row) = row else {
+            continue;
+        };
+        commands.entity(row).with_children(|pips| {
+            for index in shown..health.max {
+                pips.spawn(health_pip(player, health, index));
+            }
+        });
+    }
+}
+
+fn update_ammo(
+    players: Query<(Entity, &Gunboots), Changed<Gunboots>>,
+    mut fills: Query<(&AmmoFill, &mut Style)>,
+) {
+    for (player, gunboots) in &players {
+        for (fill, mut style) in &mut fills {
+            if fill.0 == player {
+                style.height = ammo_height(gunboots);
+            }
+        }
+    }
+}
+
+fn update_wallet(
+    players: Query<(Entity, &Wallet), Changed<Wallet>>,
+    mut texts: Query<(&WalletText, &mut Text)>,
+) {
+    for (player, wallet) in &players {
+        for (text, mut label) in &mut texts {
+            if text.0 == player {
+                label.sections[0].value = wallet_text(wallet);
+            }
+        }
+    }
+}
+
+/// Only shown once there is a combo going
+fn update_combo(
+    players: Query<(Entity, &Combo), Changed<Combo>>,
+    mut texts: Query<(&mut ComboText, &mut Text)>,
+) {
+    for (player, combo) in &players {
+        for (mut text, mut label) in &mut texts {
+            if text.player != player {
+                continue;
+            }
+            // a result still showing is left alone until a new combo starts
+            if combo.0 > 0 {
+                text.result = None;
+                label.sections[0].value = format!("{} combo", combo.0);
+            } else if text.result.is_none() {
+                label.sections[0].value.clear();
+            }
+        }
+    }
+}
+
+fn show_combo_result(
+    mut ended: EventReader<ComboEnded>,
+    mut texts: Query<(&mut ComboText, &mut Text)>,
+) {
+    for event in ended.read() {
+        for (mut text, mut label) in &mut texts {
+            if text.player != event.player {
+                continue;
+            }
+            let mut result = format!("{} combo!", event.count);
+            if event.coins > 0 {
+                result.push_str(&format!(" +{} coins", event.coins));
+            }
+            if event.max_ammo > 0 {
+                result.push_str(&format!(" +{} ammo", event.max_ammo));
+            }
+            label.sections[0].value = result;
+            text.result = Some(Timer::from_seconds(COMBO_RESULT_SECONDS, TimerMode::Once));
+        }
+    }
+}
+
+fn clear_combo_result(mut texts: Query<(&mut ComboText, &mut Text)>, time: Res<Time>) {
+    for (mut text, mut label) in &mut texts {
+        let Some(timer) = &mut text.result else {
+            continue;
+        };
+        if timer.tick(time.delta()).finished() {
+            text.result = None;
+            label.sections[0].value.clear();
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use avian2d::prelude::*;
+use bevy::{prelude::*, utils::HashSet};
+
+use crate::{
+    audio::Sound,
+    camera::CameraImpulse,
+    combo::{Combo, ComboPayout},
+    gunboots::Gunboots,
+    map::{Gameplay, Tile},
+    particles::{Burst, KNOCKOUT},
+    player::{Health, Player, Wallet},
+};
+
+pub fn plugin(app: &mut App) {
+    app.add_event::<PlayerDied>()
+        .add_systems(FixedUpdate, kill_players.in_set(Gameplay));
+}
+
+/// Any player touching this loses a point of `Health`, ending their combo, and is sent
+/// back to their `SpawnPoint`, or leaves the run if that was their last
+#[derive(Component)]
+pub struct KillZone;
+
+/// Sent when a player loses their last point of `Health` and leaves the run
+#[derive(Event, Clone, Debug)]
+pub struct PlayerDied {
+    /// the tile that did it
+    pub cause: String,
+}
+
+/// Where a player goes back to when they die
+#[derive(Component)]
+pub struct SpawnPoint(pub Vec3);
+
+type Killable = (
+    &'static SpawnPoint,
+    &'static mut Health,
+    &'static mut Transform,
+    &'static mut LinearVelocity,
+    &'static mut Combo,
+    &'static mut Wallet,
+    &'static mut Gunboots,
+);
+
+fn kill_players(
+    mut commands: Commands,
+    mut collisions: EventReader<CollisionStarted>,
+    zones: Query<Option<&Tile>, With<KillZone>>,
+    mut players: Query<Killable, With<Player>>,
+    mut died: EventWriter<PlayerDied>,
+    mut payout: ComboPayout,
+    (mut camera, mut sounds, mut bursts): (
+        EventWriter<CameraImpulse>,
+        EventWriter<Sound>,
+        EventWriter<Burst>,
+    ),
+) {
+    // touching two zones in the same tick still only costs one point of health
+    let mut hit = HashSet::new();
+    for CollisionStarted(a, b) in collisions.read() {
+        for (zone, player) in [(*a, *b), (*b, *a)] {
+            let Ok(tile) = zones.get(zone) else {
+                continue;
+            };
+            let Ok((
+                spawn,
+                mut health,
+                mut transform,
+                mut velocity,
+                mut combo,
+                mut wallet,
+                mut gunboots,
+            )) = players.get_mut(player)
+            else {
+                continue;
+            };
+            if !hit.insert(player) {
+                continue;
+            }
+            health.current -= 1;
+            payout.end(player, &mut combo, &mut wallet, &mut gunboots);
+            bursts.send(Burst {
+                emitter: KNOCKOUT,
+                at: transform.translation.truncate(),
+            });
+            if health.current == 0 {
+                commands.entity(player).despawn_recursive();
+                died.send(PlayerDied {
+                    cause: tile.map_or("Unknown".to_string(), |tile| format!("{tile:?}")),
+                });
+            } else {
+                transform.translation = spawn.0;
+                velocity.0 = Vec2::ZERO;
+            }
+            camera.send(CameraImpulse::Shake(0.6));
+            camera.send(CameraImpulse::ZoomPunch(0.1));
+            camera.send(CameraImpulse::Freeze(0.15));
+            sounds.send(Sound::Damage);
+        }
+    }
+}
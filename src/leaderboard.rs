@@ -0,0 +1,293 @@
+//! Every run that ends in a game over, kept in `leaderboard.ron` in the `SaveDir` and
+//! ranked on the results screen
+
+use std::{cmp::Ordering, path::Path};
+
+use bevy::prelude::*;
+
+use crate::{
+    game_state::{GameState, RunSummary},
+    save::{load_or_move_aside, write_atomic, SaveDir},
+    seed::Seed,
+};
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<SaveDir>();
+    let leaderboard = app.world().resource::<SaveDir>().load_leaderboard();
+    app.insert_resource(leaderboard)
+        .init_resource::<LeaderboardFilter>()
+        .add_systems(OnEnter(GameState::GameOver), record_run)
+        .add_systems(OnExit(GameState::Results), despawn_panel)
+        .add_systems(
+            Update,
+            (
+                toggle_filter,
+                (despawn_panel, spawn_panel).chain().run_if(
+                    state_changed::<GameState>.or_else(resource_changed::<LeaderboardFilter>),
+                ),
+            )
+                .chain()
+                .run_if(in_state(GameState::Results)),
+        );
+}
+
+const LEADERBOARD_FILE: &str = "leaderboard.ron";
+/// How many runs the results screen lists
+const SHOWN_RUNS: usize = 8;
+
+/// One finished run
+#[derive(serde::Deserialize, serde::Serialize, Clone, PartialEq, Debug)]
+pub struct RunRecord {
+    pub seed: u64,
+    pub map: String,
+    pub section: u32,
+    pub depth: u32,
+    pub ticks: u32,
+    pub coins: u32,
+    /// blocks broken by players, saved as `kills` before it was renamed
+    #[serde(alias = "kills")]
+    pub blocks_broken: u32,
+    pub best_combo: u32,
+    pub death: String,
+}
+
+impl RunRecord {
+    /// Further is better, then faster
+    fn rank(&self, other: &RunRecord) -> Ordering {
+        other
+            .section
+            .cmp(&self.section)
+            .then(other.depth.cmp(&self.depth))
+            .then(self.ticks.cmp(&other.ticks))
+    }
+}
+
+#[derive(Resource, serde::Deserialize, serde::Serialize, Default, Debug)]
+#[serde(default)]
+pub struct Leaderboard {
+    /// every run in the order they were played
+    pub runs: Vec<RunRecord>,
+    /// the run that just ended, if it was played this session
+    #[serde(skip)]
+    pub last: Option<usize>,
+}
+
+impl Leaderboard {
+    /// Runs from best to worst, only those played on `seed` if one is given
+    pub fn ranked(&self, seed: Option<u64>) -> Vec<(usize, &RunRecord)> {
+        let mut runs: Vec<_> = self
+            .runs
+            .iter()
+            .enumerate()
+            .filter(|(_, run)| seed.is_none_or(|seed| run.seed == seed))
+            .collect();
+        runs.sort_by(|(_, a), (_, b)| a.rank(b));
+        runs
+    }
+
+    /// Start empty if there is no file yet or it can not be read
+    fn load(path: &Path) -> Leaderboard {
+        load_or_move_aside(path, |data| ron::from_str(data).map_err(|e| e.to_string()))
+            .unwrap_or_default()
+    }
+
+    fn save(&self, path: &Path) -> Result<(), String> {
+        let data = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())
+            .map_err(|e| e.to_string())?;
+        write_atomic(path, data.as_bytes()).map_err(|e| format!("{:?}: {e}", path))
+    }
+}
+
+impl SaveDir {
+    fn load_leaderboard(&self) -> Leaderboard {
+        match self.file(LEADERBOARD_FILE) {
+            Some(path) => Leaderboard::load(&path),
+            None => Leaderboard::default(),
+        }
+    }
+}
+
+/// Whether the results screen shows every run or only those on this run's seed
+#[derive(Resource, Default)]
+pub struct LeaderboardFilter {
+    pub this_seed: bool,
+}
+
+#[derive(Component)]
+struct LeaderboardPanel;
+
+fn record_run(mut leaderboard: ResMut<Leaderboard>, run: RunSummary, dir: Res<SaveDir>) {
+    let stats = &run.stats;
+    leaderboard.runs.push(RunRecord {
+        seed: run.seed.0,
+        map: run.map.0.clone(),
+        section: run.section.number,
+        depth: stats.depth,
+        ticks: stats.ticks,
+        coins: stats.coins,
+        blocks_broken: stats.blocks_broken,
+        best_combo: stats.best_combo,
+        death: stats.death.clone().unwrap_or_else(|| "Unknown".to_string()),
+    });
+    leaderboard.last = Some(leaderboard.runs.len() - 1);
+    let Some(path) = dir.file(LEADERBOARD_FILE) else {
+        return;
+    };
+    if let Err(e) = leaderboard.save(&path) {
+        error!("{e}");
+    }
+}
+
+/// Tab or north on a gamepad switches between every run and this seed's runs
+fn toggle_filter(
+    mut filter: ResMut<LeaderboardFilter>,
+    keys: Res<ButtonInput<KeyCode>>,
+    buttons: Res<ButtonInput<GamepadButton>>,
+) {
+    let north = buttons
+        .get_just_pressed()
+        .any(|button| button.button_type == GamepadButtonType::North);
+    if keys.just_pressed(KeyCode::Tab) || north {
+        filter.this_seed = !filter.this_seed;
+    }
+}
+
+fn spawn_panel(
+    mut commands: Commands,
+    leaderboard: Res<Leaderboard>,
+    filter: Res<LeaderboardFilter>,
+    seed: Res<Seed>,
+) {
+    let ranked = leaderboard.ranked(filter.this_seed.then_some(seed.0));
+    let heading = if filter.this_seed {
+        format!("Seed {}", *seed)
+    } else {
+        "All seeds".to_string()
+    };
+    let place = leaderboard
+        .last
+        .and_then(|last| ranked.iter().position(|(index, _)| *index == last));
+    commands
+        .spawn((
+            Name::new("Leaderboard"),
+            LeaderboardPanel,
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    right: Val::Px(16.),
+                    top: Val::Px(16.),
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(4.),
+                    padding: UiRect::all(Val::Px(8.)),
+                    ..default()
+                },
+                background_color: Color::srgba(0., 0., 0., 0.6).into(),
+                z_index: ZIndex::Global(1),
+                ..default()
+            },
+        ))
+        .with_children(|panel| {
+            let small = TextStyle {
+                font_size: 16.,
+                ..default()
+            };
+            panel.spawn(TextBundle::from_section(heading, TextStyle::default()));
+            if let Some(place) = place {
+                panel.spawn(TextBundle::from_section(
+                    format!("Ranked {} of {}", place + 1, ranked.len()),
+                    TextStyle::default(),
+                ));
+            }
+            for (rank, (index, run)) in ranked.iter().enumerate().take(SHOWN_RUNS) {
+                let seconds = run.ticks as f64 / crate::FIXED_HZ;
+                let color = if Some(*index) == leaderboard.last {
+                    Color::srgb(1., 0.85, 0.3)
+                } else {
+                    Color::WHITE
+                };
+                panel.spawn(TextBundle::from_section(
+                    format!(
+                        "{}. section {} depth {}  {}:{:05.2}  {} coins  {} blocks  {} combo  {}",
+                        rank + 1,
+                        run.section,
+                        run.depth,
+                        (seconds / 60.) as u32,
+                        seconds % 60.,
+                        run.coins,
+                        run.blocks_broken,
+                        run.best_combo,
+                        run.death,
+                    ),
+                    TextStyle {
+                        color,
+                        ..small.clone()
+                    },
+                ));
+            }
+            panel.spawn(TextBundle::from_section(
+                "Tab to switch between all runs and this seed",
+                small,
+            ));
+        });
+}
+
+fn despawn_panel(mut commands: Commands, panels: Query<Entity, With<LeaderboardPanel>>) {
+    for panel in &panels {
+        commands.entity(panel).despawn_recursive();
+    }
+}
+
+#[test]
+fn runs_rank_by_distance_then_time() {
+    let run = |seed, section, depth, ticks| RunRecord {
+        seed,
+        map: String::new(),
+        section,
+        depth,
+        ticks,
+        coins: 0,
+        blocks_broken: 0,
+        best_combo: 0,
+        death: String::new(),
+    };
+    let leaderboard = Leaderboard {
+        runs: vec![
+            run(1, 1, 40, 100),
+            run(2, 2, 10, 100),
+            run(1, 1, 40, 50),
+            run(1, 1, 60, 900),
+        ],
+        last: None,
+    };
+    let order = |seed| -> Vec<usize> {
+        leaderboard
+            .ranked(seed)
+            .into_iter()
+            .map(|(index, _)| index)
+            .collect()
+    };
+    assert_eq!(order(None), [1, 3, 2, 0]);
+    assert_eq!(order(Some(1)), [3, 2, 0]);
+    assert_eq!(order(Some(3)), Vec::<usize>::new());
+}
+
+#[test]
+fn unreadable_leaderboards_are_moved_aside() {
+    let dir = std::env::temp_dir().join(format!("wowndell-leaderboard-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(LEADERBOARD_FILE);
+    std::fs::write(&path, "not a leaderboard").unwrap();
+    assert!(Leaderboard::load(&path).runs.is_empty());
+    assert!(!path.exists());
+    assert!(path.with_extension("ron.bak").exists());
+
+    // written before `kills` was renamed
+    std::fs::write(
+        &path,
+        "(runs: [(seed: 1, map: \"\", section: 1, depth: 2, ticks: 3, coins: 4, kills: 5, \
+         best_combo: 6, death: \"Void\")])",
+    )
+    .unwrap();
+    assert_eq!(Leaderboard::load(&path).runs[0].blocks_broken, 5);
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -1,38 +1,125 @@
 use avian2d::prelude::*;
-use bevy::{prelude::*, window::WindowResolution};
+use bevy::{
+    prelude::*,
+    window::{WindowMode, WindowResolution},
+};
 
+mod atlas;
+mod audio;
+mod background;
 mod camera;
+mod cli;
+mod combo;
+mod controls;
+mod destructible;
+mod editor_window;
+mod game_state;
+mod gunboots;
+mod hud;
+mod kill_zone;
+mod leaderboard;
 mod map;
+mod one_way;
+mod particles;
 mod player;
+mod progression;
+mod replay;
+mod save;
+mod seed;
+mod tuning;
 
-#[cfg(debug_assertions)]
-mod editor_window;
+#[cfg(test)]
+mod harness;
+
+const FIXED_HZ: f64 = 60.;
 
 fn main() {
+    let options = cli::Options::from_env();
     let mut app = App::new();
-    app.add_plugins((
+    app.add_plugins(
         DefaultPlugins
             .set(ImagePlugin::default_nearest())
             .set(WindowPlugin {
                 primary_window: Some(Window {
                     title: "WownDell".to_string(),
-                    resizable: false,
+                    resizable: true,
                     resolution: WindowResolution::new(480., 720.),
+                    mode: if options.fullscreen {
+                        WindowMode::BorderlessFullscreen
+                    } else {
+                        WindowMode::Windowed
+                    },
                     ..default()
                 }),
                 ..default()
             }),
-        avian2d::PhysicsPlugins::default().with_length_unit(32.),
-        leafwing_input_manager::prelude::InputManagerPlugin::<player::PlayerAction>::default(),
-    ))
-    .insert_resource(Gravity(Vec2::Y * -500.))
-    .add_plugins((player::plugin, map::plugin, camera::plugin));
+    );
+    // set before the game so its defaults do not get a say
+    if let Some(map) = &options.map {
+        app.insert_resource(map::LoadMap(map.clone()));
+    }
+    let source = seed::SeedSource {
+        fixed: options.seed,
+        daily: options.daily,
+        again: false,
+    };
+    if options.daily {
+        app.insert_resource(seed::Seed::today());
+    } else if let Some(seed) = options.seed {
+        app.insert_resource(seed);
+    }
+    app.insert_resource(source);
+    app.add_plugins(game);
+    if options.map.is_some() {
+        // asking for a map skips the title
+        app.add_systems(Startup, game_state::skip_title);
+    }
 
-    #[cfg(debug_assertions)]
+    if options.editor {
+        app.add_plugins((
+            bevy_editor_pls::EditorPlugin::default(),
+            editor_window::setup,
+        ));
+    }
+    if options.physics_debug {
+        app.add_plugins(avian2d::debug_render::PhysicsDebugPlugin::default());
+    }
+    app.run();
+}
+
+/// Everything that makes up the game, on top of either `DefaultPlugins` or the
+/// headless plugins the `harness` uses
+fn game(app: &mut App) {
     app.add_plugins((
-        bevy_editor_pls::EditorPlugin::default(),
-        avian2d::debug_render::PhysicsDebugPlugin::default(),
-        editor_window::setup,
+        avian2d::PhysicsPlugins::new(FixedPostUpdate).with_length_unit(32.),
+        leafwing_input_manager::prelude::InputManagerPlugin::<player::PlayerAction>::default(),
+    ))
+    // one physics step per fixed tick so replays play out the same
+    .insert_resource(Time::<Fixed>::from_hz(FIXED_HZ))
+    .insert_resource(Time::new_with(Physics::fixed_once_hz(FIXED_HZ)))
+    .add_plugins((
+        player::plugin,
+        map::plugin,
+        camera::plugin,
+        atlas::plugin,
+        gunboots::plugin,
+        destructible::plugin,
+        one_way::plugin,
+        kill_zone::plugin,
+        controls::plugin,
+        seed::plugin,
+        game_state::plugin,
+        replay::plugin,
+    ))
+    .add_plugins((
+        combo::plugin,
+        hud::plugin,
+        progression::plugin,
+        tuning::plugin,
+        save::plugin,
+        leaderboard::plugin,
+        audio::plugin,
+        particles::plugin,
+        background::plugin,
     ));
-    app.run();
 }
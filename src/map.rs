@@ -1,24 +1,42 @@
+use avian2d::prelude::*;
 use bevy::{
-    asset::{AssetLoader, AsyncReadExt, LoadedFolder},
+    asset::{AssetLoader, AsyncReadExt, LoadState, LoadedFolder, RecursiveDependencyLoadState},
     prelude::*,
     utils::HashMap,
 };
 use rand::{seq::IteratorRandom, SeedableRng};
 use strum::IntoEnumIterator;
 
+use crate::{
+    atlas::{AtlasDefinition, Cells, SpriteSheets},
+    destructible::Destructible,
+    gunboots::GunPickup,
+    kill_zone::KillZone,
+    one_way::OneWayPlatform,
+    particles::{ParticleEmitter, STEAM},
+    player::Climbable,
+    progression::{ShopItem, Upgrade},
+    seed::{Seed, Stream},
+};
+
 pub fn plugin(app: &mut App) {
-    app.init_resource::<Tiles>()
-        .init_asset::<TileDescriptor>()
+    app.init_asset::<TileDescriptor>()
         .register_type::<TileSprite>()
+        .register_type::<Layer>()
+        .register_type::<Option<ColumnPart>>()
         .register_asset_loader(MapLoader)
         .register_asset_loader(TileDescriptorLoader)
+        // after the loader or the folder can start loading before `.tile` files are known
+        .init_resource::<Tiles>()
         .init_state::<MapState>()
         .init_asset::<MapData>()
         .register_type::<MapData>()
         .init_resource::<CurrentMap>()
         .init_resource::<LoadMap>()
-        .init_resource::<SpriteSheet>()
-        .add_systems(Update, (update_tile, set_tile).chain())
+        .add_systems(
+            Update,
+            (refresh_tiles, update_tile, update_colliders).chain(),
+        )
         .add_systems(
             Update,
             (
@@ -27,21 +45,40 @@ pub fn plugin(app: &mut App) {
                 detect_changes.run_if(in_state(MapState::Done)),
             ),
         )
-        .add_systems(OnEnter(MapState::Spawning), spawn_map)
-        .add_systems(Last, set_done.run_if(in_state(MapState::Spawning)));
+        .add_systems(
+            OnEnter(MapState::Spawning),
+            (despawn_maps, spawn_map).chain(),
+        )
+        .add_systems(Last, set_done.run_if(in_state(MapState::Spawning)))
+        .configure_sets(FixedPreUpdate, Gameplay.run_if(in_state(MapState::Done)))
+        .configure_sets(FixedUpdate, Gameplay.run_if(in_state(MapState::Done)));
+}
+
+/// Fixed timestep systems that move the game along, nothing happens until the map is
+/// spawned so a run plays out the same no matter how long loading took
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Gameplay;
+
+/// Only one map is out at a time, whatever was there before goes when a map spawns
+fn despawn_maps(mut commands: Commands, maps: Query<Entity, With<MapEntities>>) {
+    for map in &maps {
+        commands.entity(map).despawn_recursive();
+    }
 }
 
 #[derive(Resource)]
-#[allow(dead_code)] // TODO: reason as to why, I wasn't paying full attention at this point to say exactly why - Skylark
-struct Tiles(Handle<LoadedFolder>);
+pub(crate) struct Tiles(pub Handle<LoadedFolder>);
 impl FromWorld for Tiles {
     fn from_world(world: &mut World) -> Self {
         Tiles(world.resource::<AssetServer>().load_folder("tiles"))
     }
 }
 
+/// The path of the map to play, changing it (or just marking it changed) loads and
+/// spawns the map fresh. Starts on the
+/// lobby unless `--map` says otherwise
 #[derive(Resource)]
-struct LoadMap(String);
+pub(crate) struct LoadMap(pub String);
 
 impl FromWorld for LoadMap {
     fn from_world(_: &mut World) -> Self {
@@ -49,32 +86,8 @@ impl FromWorld for LoadMap {
     }
 }
 
-#[derive(Resource)]
-struct SpriteSheet(Handle<Image>, Handle<TextureAtlasLayout>);
-
-impl SpriteSheet {
-    fn image(&self) -> Handle<Image> {
-        self.0.clone()
-    }
-
-    fn atlas(&self) -> Handle<TextureAtlasLayout> {
-        self.1.clone()
-    }
-}
-
-impl FromWorld for SpriteSheet {
-    fn from_world(world: &mut World) -> Self {
-        let image = world.resource::<AssetServer>().load("tilemap.png");
-        let atlas = TextureAtlasLayout::from_grid(UVec2::splat(8), 15, 10, None, None);
-        let atlas = world
-            .resource_mut::<Assets<TextureAtlasLayout>>()
-            .add(atlas);
-        SpriteSheet(image, atlas)
-    }
-}
-
 #[derive(Component)]
-struct TileId(IVec3);
+pub(crate) struct TileId(pub IVec3);
 
 #[derive(
     Component,
@@ -87,7 +100,10 @@ struct TileId(IVec3);
     serde::Serialize,
     Debug,
     Reflect,
+    strum_macros::IntoStaticStr,
 )]
+/// A named cell in one of the `.atlas` files, the name is what gets looked up
+/// so the order here does not need to match any sprite sheet
 pub enum TileSprite {
     Air,
     TreeTopYellow,
@@ -191,143 +207,34 @@ pub enum TileSprite {
     BlueDots,
 }
 
-impl TileSprite {
-    fn is_sold(&self) -> bool {
-        match self {
-            TileSprite::Air => false,
-            TileSprite::TreeTopYellow => true,
-            TileSprite::DirtEmpty => true,
-            TileSprite::DirtLowLeftYellow => true,
-            TileSprite::DirtLowYellow => true,
-            TileSprite::DirtLowRightYellow => true,
-            TileSprite::DirtTopSlopeUpYellow => true,
-            TileSprite::DirtTopSlopeDownYellow => true,
-            TileSprite::TreeTopPink => true,
-            TileSprite::DirtBones => true,
-            TileSprite::DirtLowLeftPink => true,
-            TileSprite::DirtLowPink => true,
-            TileSprite::DirtLowRightPink => true,
-            TileSprite::DirtTopSlopeUpPink => true,
-            TileSprite::DirtTopSlopeDownPink => true,
-            TileSprite::SpringDown => false,
-            TileSprite::TreeMiddleYellow => true,
-            TileSprite::DirtSpots => true,
-            TileSprite::DirtRightYellow => true,
-            TileSprite::DirtFullYellow => true,
-            TileSprite::DirtLeftYellow => true,
-            TileSprite::DirtRightDotYellow => true,
-            TileSprite::DirtLeftDotYellow => true,
-            TileSprite::TreeMiddlePink => true,
-            TileSprite::DirtBone => true,
-            TileSprite::DirtRightPink => true,
-            TileSprite::DirtFullPink => true,
-            TileSprite::DirtLeftPink => true,
-            TileSprite::DirtRightDotPink => true,
-            TileSprite::DirtLeftDotPink => true,
-            TileSprite::SpringUp => false,
-            TileSprite::TreeBottomYellow => true,
-            TileSprite::DirtCross => true,
-            TileSprite::DirtTopRightYellow => true,
-            TileSprite::DirtTopYellow => true,
-            TileSprite::DirtTopLeftYellow => true,
-            TileSprite::DirtBottomRightDotYellow => true,
-            TileSprite::DirtBottomLeftDotYellow => true,
-            TileSprite::TreeBottomPink => true,
-            TileSprite::DirtSkull => true,
-            TileSprite::DirtTopRightPink => true,
-            TileSprite::DirtTopPink => true,
-            TileSprite::DirtTopLeftPink => true,
-            TileSprite::DirtBottomRightDotPink => true,
-            TileSprite::DirtBottomLeftDotPink => true,
-            TileSprite::PlatformOff => false,
-            TileSprite::TreeStemYellow => false,
-            TileSprite::AllBarBottomYellow => true,
-            TileSprite::AllBarRightYellow => true,
-            TileSprite::TopBottomYellow => true,
-            TileSprite::AllBarLeftYellow => true,
-            TileSprite::DirtBottomSlopeDownYellow => true,
-            TileSprite::DirtBottomSlopeUpYellow => true,
-            TileSprite::TreeStemPink => false,
-            TileSprite::AllBarBottomPink => true,
-            TileSprite::AllBarRightPink => true,
-            TileSprite::TopBottomPink => true,
-            TileSprite::AllBarLeftPink => true,
-            TileSprite::DirtBottomSlopeDownPink => true,
-            TileSprite::DirtBottomSlopeUpPink => true,
-            TileSprite::PlatformOn => false,
-            TileSprite::TreeTrunkYellow => false,
-            TileSprite::LeftRightYellow => true,
-            TileSprite::TopLeftYellow => true,
-            TileSprite::TopRightYellow => true,
-            TileSprite::GrassYellow => false,
-            TileSprite::Key => false,
-            TileSprite::Lock => false,
-            TileSprite::TreeTrunkPink => false,
-            TileSprite::LeftRightPink => true,
-            TileSprite::TopLeftPink => true,
-            TileSprite::TopRightPink => true,
-            TileSprite::GrassPink => false,
-            TileSprite::Flag => false,
-            TileSprite::Pole => false,
-            TileSprite::Spike => false,
-            TileSprite::Void => false,
-            TileSprite::AllBarTopYellow => true,
-            TileSprite::BottomLeftYellow => true,
-            TileSprite::BottomRightYellow => true,
-            TileSprite::HeartFlowerYellow => true,
-            TileSprite::Cloud => false,
-            TileSprite::Smoke => false,
-            TileSprite::Steam => false,
-            TileSprite::AllBarTopPink => true,
-            TileSprite::BottomLeftPink => true,
-            TileSprite::BottomRightPink => true,
-            TileSprite::HeartFlowerPink => false,
-            TileSprite::Coin => false,
-            TileSprite::Magnet => false,
-            TileSprite::PlayerRedStand => false,
-            TileSprite::PlayerRedWalk => false,
-            TileSprite::PlayerRedJump => false,
-            TileSprite::PlayerRedDie => false,
-            TileSprite::RedCubeFace => false,
-            TileSprite::RedCircle => false,
-            TileSprite::RedDot => false,
-            TileSprite::BlueCircleFace => false,
-            TileSprite::BlueDiamond => false,
-            TileSprite::BlueDots => false,
-        }
-    }
-}
-
+/// The map spawns once it has loaded and every `.tile` and `.atlas` file has been
+/// tried, since which tiles collide comes from the sheets. Sheets or tiles that fail
+/// to load leave those tiles without sprites rather than holding the map back
 fn check_loaded(
-    mut load_map: EventReader<AssetEvent<MapData>>,
     mut next: ResMut<NextState<MapState>>,
     current: Res<CurrentMap>,
+    (tiles, sheets): (Res<Tiles>, Res<SpriteSheets>),
+    asset_server: Res<AssetServer>,
 ) {
-    for event in load_map.read() {
-        match event {
-            AssetEvent::Added { id }
-            | AssetEvent::Modified { id }
-            | AssetEvent::LoadedWithDependencies { id } => {
-                if *id == current.0.id() {
-                    next.set(MapState::Spawning);
-                }
-            }
-            _ => {}
-        }
-    }
-}
-
-fn set_tile(mut tiles: Query<(&TileSprite, &mut TextureAtlas), Changed<TileSprite>>) {
-    for (tile, mut atlas) in &mut tiles {
-        atlas.index = *tile as usize;
+    let tried = |folder: &Handle<LoadedFolder>| {
+        matches!(
+            asset_server.get_recursive_dependency_load_state(folder),
+            Some(RecursiveDependencyLoadState::Loaded | RecursiveDependencyLoadState::Failed)
+        )
+    };
+    if asset_server.load_state(&current.0) == LoadState::Loaded
+        && tried(&tiles.0)
+        && tried(&sheets.0)
+    {
+        next.set(MapState::Spawning);
     }
 }
 
 #[derive(Resource, Default)]
-struct CurrentMap(Handle<MapData>);
+pub(crate) struct CurrentMap(pub Handle<MapData>);
 
 #[derive(States, Default, Debug, Hash, PartialEq, Eq, Clone)]
-enum MapState {
+pub(crate) enum MapState {
     #[default]
     Loading,
     Spawning,
@@ -348,57 +255,145 @@ fn set_done(mut next: ResMut<NextState<MapState>>) {
     next.set(MapState::Done);
 }
 
-fn spawn_map(
-    mut commands: Commands,
-    sprite_sheet: Res<SpriteSheet>,
-    map_data: Res<Assets<MapData>>,
-    target: Res<CurrentMap>,
-) {
+fn spawn_map(mut commands: Commands, map_data: Res<Assets<MapData>>, target: Res<CurrentMap>) {
     let Some(map_data) = map_data.get(target.0.id()) else {
         error!("Map Not Loaded");
         return;
     };
     let mut map_entities = MapEntities::new();
+    let bounds = MapBounds::from_blocks(&map_data.blocks);
     commands
-        .spawn(SpatialBundle::default())
+        .spawn((SpatialBundle::default(), bounds))
         .with_children(|map| {
+            for side in [-1., 1.] {
+                map.spawn((
+                    Name::new("Well Wall"),
+                    SpatialBundle {
+                        transform: Transform::from_xyz(
+                            side * (WELL_HALF_WIDTH + 16.),
+                            bounds.0.center().y,
+                            0.,
+                        ),
+                        ..default()
+                    },
+                    RigidBody::Static,
+                    Collider::rectangle(32., bounds.0.height() + WELL_WALL_OVERHANG * 2.),
+                ));
+            }
             for block in map_data.blocks.iter() {
                 let id = block.translation;
+                let layer = Layer::from_z(id.z);
                 if map_entities.empty(id) {
-                    map_entities.add(
-                        id,
-                        map.spawn((
-                            SpriteBundle {
-                                transform: Transform::from_translation(
-                                    block.translation.as_vec3() * 32.,
-                                ),
-                                texture: sprite_sheet.image(),
-                                sprite: Sprite {
-                                    custom_size: Some(Vec2::splat(32.)),
-                                    ..default()
-                                },
+                    let mut tile = map.spawn((
+                        SpriteBundle {
+                            transform: Transform::from_translation(
+                                (block.translation.xy().as_vec2() * 32.).extend(layer.depth(id.z)),
+                            ),
+                            sprite: Sprite {
+                                custom_size: Some(Vec2::splat(32.)),
                                 ..default()
                             },
-                            TextureAtlas {
-                                layout: sprite_sheet.atlas(),
-                                index: 0,
-                            },
-                            TileSprite::Air,
-                            block.color,
-                            block.tile,
-                            block.variant,
-                            TileId(id),
-                        ))
-                        .id(),
-                    );
+                            visibility: Visibility::Hidden,
+                            ..default()
+                        },
+                        TextureAtlas::default(),
+                        TileSprite::Air,
+                        block.color,
+                        block.tile,
+                        block.variant,
+                        layer,
+                        TileId(id),
+                    ));
+                    if layer.has_colliders() {
+                        match block.tile {
+                            Tile::Cloud => {
+                                tile.insert((
+                                    RigidBody::Static,
+                                    Collider::rectangle(32., 32.),
+                                    OneWayPlatform::default(),
+                                ));
+                            }
+                            Tile::Void => {
+                                tile.insert((
+                                    RigidBody::Static,
+                                    Collider::rectangle(32., 32.),
+                                    Sensor,
+                                    KillZone,
+                                ));
+                            }
+                            Tile::Collectable => {
+                                tile.insert((RigidBody::Static, Collider::circle(12.), Sensor));
+                            }
+                            Tile::Tree if block.climbable => {
+                                tile.insert((
+                                    RigidBody::Static,
+                                    Collider::rectangle(16., 32.),
+                                    Sensor,
+                                    Climbable,
+                                ));
+                            }
+                            _ => {
+                                tile.insert(SolidIfCellIs);
+                            }
+                        }
+                    }
+                    if block.tile == Tile::Breakable {
+                        tile.insert(Destructible {
+                            health: block.health,
+                            coins: block.coins,
+                        });
+                    }
+                    if let Some((upgrade, price)) = block.sells {
+                        tile.insert(ShopItem { upgrade, price });
+                    }
+                    if let Some(gun) = &block.gun {
+                        tile.insert(GunPickup(gun.clone()));
+                    }
+                    if block.steam {
+                        tile.insert(ParticleEmitter::new(STEAM, STEAM_PER_SECOND));
+                    }
+                    map_entities.add(id, tile.id());
                 }
             }
         })
         .insert(map_entities);
 }
 
+/// How many puffs a steam vent lets out
+const STEAM_PER_SECOND: f32 = 3.;
+/// Half the width of the inside of the well, the walls sit just outside this
+const WELL_HALF_WIDTH: f32 = 224.;
+/// How far past the top and bottom tile the walls keep going
+const WELL_WALL_OVERHANG: f32 = 360.;
+
+/// The area the map covers in world space, the camera is kept inside it
+#[derive(Component, Clone, Copy)]
+pub struct MapBounds(pub Rect);
+
+impl MapBounds {
+    fn from_blocks(blocks: &[Block]) -> MapBounds {
+        let (min, max) = blocks
+            .iter()
+            .map(|block| block.translation.y as f32 * 32.)
+            .fold((f32::MAX, f32::MIN), |(min, max), y| {
+                (min.min(y), max.max(y))
+            });
+        if min > max {
+            return MapBounds(Rect::new(-WELL_HALF_WIDTH, 0., WELL_HALF_WIDTH, 0.));
+        }
+        MapBounds(Rect::new(
+            -WELL_HALF_WIDTH,
+            min - 16.,
+            WELL_HALF_WIDTH,
+            max + 16.,
+        ))
+    }
+}
+
+/// Every tile in the map keyed by its full position, including Z,
+/// so each layer only ever sees its own neighbours when autotiling
 #[derive(Component)]
-struct MapEntities(HashMap<IVec3, Entity>);
+pub(crate) struct MapEntities(HashMap<IVec3, Entity>);
 
 impl MapEntities {
     fn new() -> Self {
@@ -418,18 +413,35 @@ impl MapEntities {
         !self.0.contains_key(&pos)
     }
 
-    fn get(&self, id: IVec3) -> Option<Entity> {
+    pub(crate) fn get(&self, id: IVec3) -> Option<Entity> {
         self.0.get(&id).copied()
     }
+
+    pub(crate) fn remove(&mut self, id: IVec3) -> Option<Entity> {
+        self.0.remove(&id)
+    }
 }
 
 #[derive(Asset, Reflect)]
-struct MapData {
+pub(crate) struct MapData {
     blocks: Vec<Block>,
+    /// the track from the sound file that plays while this map is out
+    pub music: Option<String>,
+    /// the name of a file in `assets/backgrounds` to draw behind the map
+    pub background: Option<String>,
 }
 
 #[derive(
-    Reflect, Component, Clone, Copy, serde::Deserialize, serde::Serialize, PartialEq, Eq, Debug,
+    Reflect,
+    Component,
+    Clone,
+    Copy,
+    serde::Deserialize,
+    serde::Serialize,
+    PartialEq,
+    Eq,
+    Hash,
+    Debug,
 )]
 pub enum Tile {
     Air,
@@ -438,28 +450,37 @@ pub enum Tile {
     Spring,
     Platform,
     Collectable,
+    Breakable,
+    Cloud,
+    Void,
 }
 
 impl Tile {
-    fn is_solid(&self) -> bool {
-        match self {
-            Tile::Air => false,
-            Tile::Dirt => true,
-            Tile::Tree => false,
-            Tile::Spring => false,
-            Tile::Platform => false,
-            Tile::Collectable => false,
-        }
+    /// tiles that resolve their sprite from how tall the stack they are in is
+    fn is_column(&self) -> bool {
+        matches!(self, Tile::Tree)
     }
 
     fn parse<'a, T: Iterator<Item = &'a str>>(self, mut words: T) -> Result<Block, &'static str> {
         match self {
-            Tile::Dirt => {
+            Tile::Dirt
+            | Tile::Breakable
+            | Tile::Cloud
+            | Tile::Void
+            | Tile::Tree
+            | Tile::Collectable => {
                 let mut block = Block {
                     tile: self,
                     color: Team::None,
                     variant: Variant::Default,
                     translation: IVec3::default(),
+                    health: 1,
+                    coins: 0,
+                    height: 1,
+                    climbable: false,
+                    sells: None,
+                    gun: None,
+                    steam: false,
                 };
                 while let Some(word) = words.next() {
                     match word.trim().to_lowercase().as_str() {
@@ -490,6 +511,9 @@ impl Tile {
                             };
                             block.translation.z = num;
                         }
+                        "background" => block.translation.z = -1,
+                        "collision" => block.translation.z = 0,
+                        "foreground" => block.translation.z = 1,
                         "rand" | "random" => block.variant = Variant::Random,
                         "variant" => {
                             let Some(num) = words.next() else {
@@ -502,6 +526,59 @@ impl Tile {
                         }
                         "yellow" => block.color = Team::Yellow,
                         "pink" => block.color = Team::Pink,
+                        "hp" | "health" => {
+                            let Some(num) = words.next() else {
+                                return Err("No number after 'hp'");
+                            };
+                            let Ok(num) = num.parse() else {
+                                return Err("Word after 'hp' is not a u8");
+                            };
+                            block.health = num;
+                        }
+                        "height" => {
+                            let Some(num) = words.next() else {
+                                return Err("No number after 'height'");
+                            };
+                            let Ok(num) = num.parse() else {
+                                return Err("Word after 'height' is not a u8");
+                            };
+                            block.height = num;
+                        }
+                        "climbable" => block.climbable = true,
+                        "steam" => block.steam = true,
+                        "coins" => {
+                            let Some(num) = words.next() else {
+                                return Err("No number after 'coins'");
+                            };
+                            let Ok(num) = num.parse() else {
+                                return Err("Word after 'coins' is not a u8");
+                            };
+                            block.coins = num;
+                        }
+                        "sells" => {
+                            let Some(upgrade) = words.next() else {
+                                return Err("No upgrade after 'sells'");
+                            };
+                            let Ok(upgrade) = upgrade.parse() else {
+                                return Err("Word after 'sells' is not an upgrade");
+                            };
+                            let Some(num) = words.next() else {
+                                return Err("No price after 'sells'");
+                            };
+                            let Ok(num) = num.parse() else {
+                                return Err("Price after 'sells' is not a u32");
+                            };
+                            block.sells = Some((upgrade, num));
+                        }
+                        "gun" if self != Tile::Collectable => {
+                            return Err("Only a Collectable can hold a 'gun'");
+                        }
+                        "gun" => {
+                            let Some(gun) = words.next() else {
+                                return Err("No gun after 'gun'");
+                            };
+                            block.gun = Some(gun.to_string());
+                        }
                         e => {
                             error!("Unknown word {e}");
                         }
@@ -510,10 +587,8 @@ impl Tile {
                 Ok(block)
             }
             Tile::Air => todo!(),
-            Tile::Tree => todo!(),
             Tile::Spring => todo!(),
             Tile::Platform => todo!(),
-            Tile::Collectable => todo!(),
         }
     }
 }
@@ -539,6 +614,206 @@ pub enum Team {
     None,
 }
 
+/// Which layer of the map a tile lives in, derived from its Z coordinate.
+/// Tiles only autotile against neighbours that share the same Z.
+#[derive(Reflect, Component, Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum Layer {
+    /// decoration drawn behind the player, Z < 0
+    Background,
+    /// the tiles the player stands on, Z == 0
+    #[default]
+    Collision,
+    /// decoration drawn in front of the player, Z > 0
+    Foreground,
+}
+
+impl Layer {
+    fn from_z(z: i32) -> Layer {
+        match z {
+            ..=-1 => Layer::Background,
+            0 => Layer::Collision,
+            1.. => Layer::Foreground,
+        }
+    }
+
+    /// The Z to render a tile at, keeps every layer in its own band
+    /// so the player (at 0) always sits between background and foreground
+    fn depth(&self, z: i32) -> f32 {
+        match self {
+            Layer::Background => -10. + z as f32 * 0.1,
+            Layer::Collision => -1.,
+            Layer::Foreground => 10. + z as f32 * 0.1,
+        }
+    }
+
+    fn has_colliders(&self) -> bool {
+        match self {
+            Layer::Background => false,
+            Layer::Collision => true,
+            Layer::Foreground => false,
+        }
+    }
+}
+
+#[test]
+fn layers_sit_either_side_of_the_player() {
+    assert_eq!(Layer::from_z(-3), Layer::Background);
+    assert_eq!(Layer::from_z(-1), Layer::Background);
+    assert_eq!(Layer::from_z(0), Layer::Collision);
+    assert_eq!(Layer::from_z(2), Layer::Foreground);
+    assert!(Layer::Background.depth(-1) < Layer::Collision.depth(0));
+    assert!(Layer::Collision.depth(0) < 0.);
+    assert!(Layer::Foreground.depth(1) > 0.);
+    // deeper background tiles draw further back
+    assert!(Layer::Background.depth(-2) < Layer::Background.depth(-1));
+}
+
+/// Stand in for the `.atlas` files with one sheet where only `solid` are solid
+#[cfg(test)]
+fn insert_sheet(world: &mut World, solid: &[TileSprite]) {
+    use crate::atlas::CellDefinition;
+
+    let cells = TileSprite::iter()
+        .enumerate()
+        .map(|(index, sprite)| CellDefinition {
+            name: <&'static str>::from(sprite).to_string(),
+            index,
+            solid: solid.contains(&sprite),
+            tags: Vec::new(),
+            animation: None,
+        })
+        .collect();
+    let mut atlases = Assets::<AtlasDefinition>::default();
+    let sheet = atlases.add(AtlasDefinition::from_cells(cells));
+    let mut folders = Assets::<LoadedFolder>::default();
+    let folder = folders.add(LoadedFolder {
+        handles: vec![sheet.untyped()],
+    });
+    world.insert_resource(atlases);
+    world.insert_resource(folders);
+    world.insert_resource(SpriteSheets(folder));
+}
+
+#[test]
+fn colliders_come_from_the_sheet() {
+    use bevy::ecs::system::RunSystemOnce;
+
+    let mut world = World::new();
+    insert_sheet(&mut world, &[TileSprite::DirtBone]);
+    let tile = world.spawn((TileSprite::DirtBone, SolidIfCellIs)).id();
+    world.run_system_once(update_colliders);
+    assert!(world.get::<Collider>(tile).is_some());
+
+    *world.get_mut::<TileSprite>(tile).unwrap() = TileSprite::DirtEmpty;
+    world.run_system_once(update_colliders);
+    assert!(world.get::<Collider>(tile).is_none());
+}
+
+#[test]
+fn other_layers_do_not_change_collision_edges() {
+    use bevy::ecs::system::RunSystemOnce;
+
+    // dirt with nothing around it gets one sprite, anything next to it swaps to another
+    let alone = TileDescriptor {
+        priority: 1,
+        tile: Tile::Dirt,
+        team: Team::Any,
+        can_be_solid: [false; 8],
+        variants: vec![TileSprite::DirtEmpty],
+        ..TileDescriptor::new()
+    };
+    let edged = TileDescriptor {
+        tile: Tile::Dirt,
+        team: Team::Any,
+        variants: vec![TileSprite::DirtBone],
+        ..TileDescriptor::new()
+    };
+    let resolve = |neighbours: &[(IVec3, Tile)]| {
+        let mut world = World::new();
+        let mut descriptors = Assets::<TileDescriptor>::default();
+        descriptors.add(alone.clone());
+        descriptors.add(edged.clone());
+        world.insert_resource(descriptors);
+        world.insert_resource(Seed(0));
+        insert_sheet(&mut world, &[TileSprite::DirtEmpty, TileSprite::DirtBone]);
+        let mut map = MapEntities::new();
+        let mut spawn = |id: IVec3, tile: Tile| {
+            let entity = world
+                .spawn((
+                    TileSprite::Air,
+                    TileId(id),
+                    tile,
+                    Team::None,
+                    Variant::Default,
+                ))
+                .id();
+            map.add(id, entity);
+            entity
+        };
+        let dirt = spawn(IVec3::ZERO, Tile::Dirt);
+        for &(id, tile) in neighbours {
+            spawn(id, tile);
+        }
+        world.spawn(map);
+        world.run_system_once(update_tile);
+        *world.get::<TileSprite>(dirt).unwrap()
+    };
+
+    assert_eq!(resolve(&[]), TileSprite::DirtEmpty);
+    assert_eq!(
+        resolve(&[(IVec3::new(1, 0, 0), Tile::Dirt)]),
+        TileSprite::DirtBone
+    );
+    // a tree and a wall of dirt behind it, none of which touch the collision layer
+    let background = Adjacencies::iter()
+        .map(|adjacent| (IVec3::new(0, 0, -1) + adjacent, Tile::Dirt))
+        .chain([(IVec3::new(0, 0, -1), Tile::Tree)])
+        .collect::<Vec<_>>();
+    assert_eq!(resolve(&background), TileSprite::DirtEmpty);
+}
+
+/// Where a tile sits in a vertical run of the same tile, top to bottom
+#[derive(
+    Reflect, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize, Debug, Default,
+)]
+pub enum ColumnPart {
+    #[default]
+    Top,
+    Middle,
+    Bottom,
+    Stem,
+    Trunk,
+}
+
+impl ColumnPart {
+    /// `below` and `above` are how many of the same tile are directly under and over this one,
+    /// only the first 3 below and 1 above matter
+    fn from_run(below: usize, above: usize) -> ColumnPart {
+        match (below, above) {
+            (_, 0) => ColumnPart::Top,
+            (0, _) => ColumnPart::Trunk,
+            (1, _) => ColumnPart::Stem,
+            (2, _) => ColumnPart::Bottom,
+            _ => ColumnPart::Middle,
+        }
+    }
+}
+
+#[test]
+fn column_parts() {
+    use ColumnPart::*;
+    let column = |height: usize| {
+        (0..height)
+            .map(|i| ColumnPart::from_run(i.min(3), (height - i - 1).min(1)))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(column(1), [Top]);
+    assert_eq!(column(2), [Trunk, Top]);
+    assert_eq!(column(3), [Trunk, Stem, Top]);
+    assert_eq!(column(4), [Trunk, Stem, Bottom, Top]);
+    assert_eq!(column(6), [Trunk, Stem, Bottom, Middle, Middle, Top]);
+}
+
 #[derive(Reflect, Component, Clone, Copy)]
 pub enum Variant {
     Default,
@@ -546,16 +821,30 @@ pub enum Variant {
     Fixed(u8),
 }
 
-#[derive(Reflect)]
+#[derive(Reflect, Clone)]
 struct Block {
     tile: Tile,
     color: Team,
     translation: IVec3,
     variant: Variant,
+    /// only used by `Tile::Breakable`
+    health: u8,
+    /// only used by `Tile::Breakable`
+    coins: u8,
+    /// only used by `Tile::Tree`, how many tiles tall the column is from `translation` up
+    height: u8,
+    /// only used by `Tile::Tree`, adds a sensor the player can climb when on the collision layer
+    climbable: bool,
+    /// an upgrade and its price in coins, players buy it by standing on the tile and pressing down
+    sells: Option<(Upgrade, u32)>,
+    /// only used by `Tile::Collectable`, the gun module picked up by touching it
+    gun: Option<String>,
+    /// puffs of steam rise out of the tile
+    steam: bool,
 }
 
 #[derive(strum_macros::EnumIter, Clone, Copy, PartialEq, Eq)]
-enum Adjacencies {
+pub(crate) enum Adjacencies {
     UpLeft,
     Up,
     UpRight,
@@ -603,14 +892,40 @@ impl std::ops::Add<Adjacencies> for IVec3 {
     }
 }
 
+type ChangedTile = Or<(Changed<Tile>, Changed<Team>, Changed<Variant>)>;
+
+/// Tiles that spawned before every `.tile` file had loaded, or whose descriptors
+/// were just edited, need to pick their sprites again
+fn refresh_tiles(
+    mut events: EventReader<AssetEvent<TileDescriptor>>,
+    mut sheet_events: EventReader<AssetEvent<AtlasDefinition>>,
+    mut tiles: Query<&mut Tile>,
+) {
+    let reload = events.read().any(|event| {
+        matches!(
+            event,
+            AssetEvent::Added { .. }
+                | AssetEvent::Modified { .. }
+                | AssetEvent::LoadedWithDependencies { .. }
+        )
+    });
+    // which cells are solid decides what counts as an edge
+    let reload = sheet_events.read().count() > 0 || reload;
+    if !reload {
+        return;
+    }
+    for mut tile in &mut tiles {
+        tile.set_changed();
+    }
+}
+
 fn update_tile(
-    mut sprites: Query<
-        (&mut TileSprite, &TileId, &Tile, &Team, &Variant),
-        Or<(Changed<Tile>, Changed<Team>, Changed<Variant>)>,
-    >,
+    mut sprites: Query<(&mut TileSprite, &TileId, &Tile, &Team, &Variant), ChangedTile>,
     tiles: Query<(&Tile, &Team, &Variant)>,
     map: Query<&MapEntities>,
     tile_descriptors: Res<Assets<TileDescriptor>>,
+    seed: Res<Seed>,
+    cells: Cells,
 ) {
     let Ok(map) = map.get_single() else {
         return;
@@ -620,6 +935,14 @@ fn update_tile(
         .map(|d| d.1)
         .cloned()
         .collect::<Vec<_>>();
+    // a tile is solid to its neighbours if any sprite it could pick is
+    let mut solid = HashMap::<Tile, bool>::default();
+    for descriptor in &tile_descriptors {
+        *solid.entry(descriptor.tile).or_default() |= descriptor
+            .variants
+            .iter()
+            .any(|sprite| cells.is_solid(*sprite));
+    }
     for (mut sprite, id, tile, team, variant) in &mut sprites {
         let mut tile_builder = TileSpriteBuilder::new(tile_descriptors.clone());
         tile_builder.set_team(*team);
@@ -628,12 +951,26 @@ fn update_tile(
         for adjacent in Adjacencies::iter() {
             if let Some(id) = map.get(id.0 + adjacent) {
                 if let Ok(to) = tiles.get(id) {
-                    tile_builder.set_adjacent(adjacent, to.0.is_solid());
+                    tile_builder.set_adjacent(adjacent, solid.get(to.0).copied().unwrap_or(false));
                 }
             }
         }
-        tile_builder
-            .set_seed((((id.0.x as u64) << 32) ^ id.0.y as u64).wrapping_add(id.0.z as u64));
+        if tile.is_column() {
+            let run = |step: IVec3| {
+                (1..=3)
+                    .take_while(|&i| {
+                        map.get(id.0 + step * i)
+                            .and_then(|e| tiles.get(e).ok())
+                            .is_some_and(|to| to.0 == tile)
+                    })
+                    .count()
+            };
+            tile_builder.set_column(ColumnPart::from_run(run(IVec3::NEG_Y), run(IVec3::Y)));
+        }
+        tile_builder.set_seed(seed.derive(
+            Stream::Tiles,
+            &[id.0.x as i64, id.0.y as i64, id.0.z as i64],
+        ));
         tile_builder.resolve();
 
         if let Some(to) = tile_builder.result() {
@@ -644,6 +981,30 @@ fn update_tile(
     }
 }
 
+/// On a tile with no collider of its own, it collides as a full block while its
+/// sprite's cell is solid
+#[derive(Component)]
+struct SolidIfCellIs;
+
+type ChangedSolidity = (With<SolidIfCellIs>, Changed<TileSprite>);
+
+fn update_colliders(
+    mut commands: Commands,
+    tiles: Query<(Entity, &TileSprite, Has<Collider>), ChangedSolidity>,
+    cells: Cells,
+) {
+    for (entity, sprite, has_collider) in &tiles {
+        let solid = cells.is_solid(*sprite);
+        if solid && !has_collider {
+            commands
+                .entity(entity)
+                .insert((RigidBody::Static, Collider::rectangle(32., 32.)));
+        } else if !solid && has_collider {
+            commands.entity(entity).remove::<(RigidBody, Collider)>();
+        }
+    }
+}
+
 struct TileSpriteBuilder {
     seed: u64,
     tiles: Vec<TileDescriptor>,
@@ -651,6 +1012,7 @@ struct TileSpriteBuilder {
     team: Option<Team>,
     variant: Variant,
     adjacent_solid: [bool; 8],
+    column: Option<ColumnPart>,
 }
 
 impl TileSpriteBuilder {
@@ -662,6 +1024,7 @@ impl TileSpriteBuilder {
             tile: None,
             team: None,
             adjacent_solid: [false; 8],
+            column: None,
         }
     }
 
@@ -681,6 +1044,10 @@ impl TileSpriteBuilder {
         self.team = Some(team)
     }
 
+    fn set_column(&mut self, part: ColumnPart) {
+        self.column = Some(part)
+    }
+
     fn resolve(&mut self) -> bool {
         if let Some(team) = self.team {
             self.tiles.retain(|sprite| sprite.is_team(team));
@@ -690,8 +1057,13 @@ impl TileSpriteBuilder {
         }
         self.tiles
             .retain(|tiles| tiles.test_solid(self.adjacent_solid));
+        if let Some(part) = self.column {
+            self.tiles
+                .retain(|tiles| tiles.column.is_none_or(|column| column == part));
+        }
 
-        self.tiles.sort_by(|a, b| b.priority.cmp(&a.priority));
+        self.tiles
+            .sort_by_key(|tile| std::cmp::Reverse(tile.priority));
         true
     }
 
@@ -724,6 +1096,9 @@ pub struct TileDescriptor {
     pub can_be_solid: [bool; 8],
     pub must_be_solid: [bool; 8],
     pub variants: Vec<TileSprite>,
+    /// for tiles that stack into columns, the part of the column this is for
+    #[serde(default)]
+    pub column: Option<ColumnPart>,
 }
 
 impl TileDescriptor {
@@ -736,6 +1111,7 @@ impl TileDescriptor {
             can_be_solid: [true; 8],
             must_be_solid: [false; 8],
             variants: Vec::new(),
+            column: None,
         }
     }
 }
@@ -752,6 +1128,7 @@ fn ron_out() {
 }
 
 impl TileDescriptor {
+    #[cfg(test)]
     fn all() -> Vec<TileDescriptor> {
         vec![
             TileDescriptor {
@@ -766,6 +1143,7 @@ impl TileDescriptor {
                     TileSprite::DirtBone,
                     TileSprite::DirtBones,
                 ],
+                column: None,
             },
             TileDescriptor {
                 priority: -1,
@@ -779,6 +1157,7 @@ impl TileDescriptor {
                     TileSprite::DirtBone,
                     TileSprite::DirtBones,
                 ],
+                column: None,
             },
         ]
     }
@@ -795,8 +1174,8 @@ impl TileDescriptor {
     }
 
     fn test_solid(&self, solid: [bool; 8]) -> bool {
-        for i in 0..8 {
-            if solid[i] {
+        for (i, solid) in solid.into_iter().enumerate() {
+            if solid {
                 if !self.can_be_solid[i] {
                     return false;
                 }
@@ -832,44 +1211,84 @@ async fn load_map<'a>(
     if reader.read_to_string(&mut data).await.is_err() {
         return Err("Failed to read to string");
     };
+    MapData::parse(&data)
+}
 
-    let mut blocks = vec![];
-    for line in data.lines() {
-        let line = line.trim();
-        if line.is_empty() {
-            continue;
-        }
-        let mut words = line.split_whitespace();
-        match words
-            .next()
-            .expect("at least one word")
-            .to_lowercase()
-            .as_str()
-        {
-            "dirt" => blocks.push(Tile::Dirt.parse(words)?),
-            e => {
-                error!("{e} is not a valise tile type");
+impl MapData {
+    /// One block per line, starting with what kind of tile it is. `Music <track>` and
+    /// `Background <name>` on lines of their own set the map's music and background
+    pub(crate) fn parse(data: &str) -> Result<MapData, &'static str> {
+        let mut blocks = vec![];
+        let mut music = None;
+        let mut background = None;
+        for line in data.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            match words
+                .next()
+                .expect("at least one word")
+                .to_lowercase()
+                .as_str()
+            {
+                "dirt" => blocks.push(Tile::Dirt.parse(words)?),
+                "breakable" => blocks.push(Tile::Breakable.parse(words)?),
+                "cloud" => blocks.push(Tile::Cloud.parse(words)?),
+                "void" => blocks.push(Tile::Void.parse(words)?),
+                "collectable" => blocks.push(Tile::Collectable.parse(words)?),
+                "music" => {
+                    let Some(track) = words.next() else {
+                        return Err("No track name after 'Music'");
+                    };
+                    music = Some(track.to_string());
+                }
+                "background" => {
+                    let Some(name) = words.next() else {
+                        return Err("No background name after 'Background'");
+                    };
+                    background = Some(name.to_string());
+                }
+                "tree" => {
+                    let tree = Tile::Tree.parse(words)?;
+                    for y in 0..tree.height as i32 {
+                        blocks.push(Block {
+                            translation: tree.translation + IVec3::Y * y,
+                            ..tree.clone()
+                        });
+                    }
+                }
+                e => {
+                    error!("{e} is not a valise tile type");
+                }
             }
         }
+
+        Ok(MapData {
+            blocks,
+            music,
+            background,
+        })
     }
+}
 
-    Ok(MapData { blocks })
+#[test]
+fn only_collectables_hold_guns() {
+    assert!(MapData::parse("Collectable X 0 Y 0 gun triple").is_ok());
+    assert!(MapData::parse("Dirt X 0 Y 0 gun triple").is_err());
+    assert!(MapData::parse("Breakable X 0 Y 0 gun triple").is_err());
 }
 
 fn detect_changes(
-    mut commands: Commands,
     mut events: EventReader<AssetEvent<MapData>>,
     mut next: ResMut<NextState<MapState>>,
-    maps: Query<Entity, With<MapEntities>>,
     current: Res<CurrentMap>,
 ) {
     for event in events.read() {
         if let AssetEvent::Modified { id } = event {
             if *id == current.0.id() {
                 next.set(MapState::Spawning);
-                for map in &maps {
-                    commands.entity(map).despawn_recursive();
-                }
             }
         }
     }
@@ -894,8 +1313,8 @@ impl AssetLoader for TileDescriptorLoader {
     }
 }
 
-async fn load_tile_descriptor<'a>(
-    reader: &'a mut bevy::asset::io::Reader<'_>,
+async fn load_tile_descriptor(
+    reader: &mut bevy::asset::io::Reader<'_>,
 ) -> Result<TileDescriptor, &'static str> {
     let mut data = String::new();
     if reader.read_to_string(&mut data).await.is_err() {
@@ -1,13 +1,15 @@
 use avian2d::prelude::{Collider, RigidBody};
 use bevy::prelude::*;
-use rand::{seq::IteratorRandom, Rng, SeedableRng};
+use rand::{seq::IteratorRandom, Rng};
 use strum::IntoEnumIterator;
 
-use crate::player::Player;
+use crate::{
+    player::Player,
+    seed::{Seed, Stream},
+};
 
 pub fn plugin(app: &mut App) {
     app.init_resource::<SpriteSheet>()
-        .init_resource::<Seed>()
         .add_systems(Update, (spawn_walls, cull_walls, spawn_layer));
 }
 
@@ -62,27 +64,16 @@ fn test_atlas(mut commands: Commands, atlas: Res<SpriteSheet>) {
 #[derive(Component)]
 struct Wall;
 
-#[derive(Resource)]
-struct Seed(u64);
-
-impl FromWorld for Seed {
-    fn from_world(world: &mut World) -> Self {
-        #[cfg(not(debug_assertions))]
-        return Seed(rand::thread_rng().gen());
-
-        #[cfg(debug_assertions)]
-        Seed(42069)
-    }
-}
-
 fn cull_walls(
     mut commands: Commands,
     walls: Query<(Entity, &Transform), With<Wall>>,
     player: Query<&Transform, With<Player>>,
 ) {
-    let player = player.single().translation;
     for (id, wall) in &walls {
-        if wall.translation.distance_squared(player) > 1250000. {
+        if player
+            .iter()
+            .all(|player| wall.translation.distance_squared(player.translation) > 1250000.)
+        {
             commands.entity(id).despawn_recursive();
         }
     }
@@ -94,7 +85,14 @@ fn spawn_walls(
     mut last: Local<isize>,
     sprite_sheet: Res<SpriteSheet>,
 ) {
-    let player = player.single().translation.y;
+    // the lowest player is the one closest to running out of walls
+    let Some(player) = player
+        .iter()
+        .map(|player| player.translation.y)
+        .reduce(f32::min)
+    else {
+        return;
+    };
     let cell = (player / 32.) as isize;
     if cell - 16 < *last {
         for layer in (cell - 32)..*last {
@@ -146,12 +144,17 @@ fn spawn_layer(
     sprite_sheet: Res<SpriteSheet>,
     seed: Res<Seed>,
 ) {
-    let player = player.single().translation.y;
+    let Some(player) = player
+        .iter()
+        .map(|player| player.translation.y)
+        .reduce(f32::min)
+    else {
+        return;
+    };
     let layer = (player / 128.) as isize;
     if layer - 4 < *last {
         for layer in (layer - 8)..*last {
-            let mut rng =
-                rand::rngs::StdRng::seed_from_u64(seed.0.wrapping_add(layer as u64) ^ seed.0);
+            let mut rng = seed.rng(Stream::Chunks, &[layer as i64]);
             if !rng.gen_bool(0.25) {
                 continue;
             }
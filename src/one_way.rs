@@ -0,0 +1,86 @@
+use avian2d::prelude::*;
+use bevy::{prelude::*, utils::HashSet};
+use leafwing_input_manager::prelude::*;
+
+use crate::{
+    map::Gameplay,
+    player::{Player, PlayerAction},
+};
+
+pub fn plugin(app: &mut App) {
+    app.add_systems(FixedUpdate, drop_through.in_set(Gameplay))
+        .add_systems(PostProcessCollisions, one_way_platforms);
+}
+
+/// Only collides with things landing on it from above,
+/// keeps track of everything currently passing through it
+#[derive(Component, Default)]
+pub struct OneWayPlatform(HashSet<Entity>);
+
+/// Added to a player while they are holding down to fall through one way platforms
+#[derive(Component)]
+pub struct DropThrough;
+
+fn drop_through(
+    mut commands: Commands,
+    players: Query<(Entity, &ActionState<PlayerAction>), With<Player>>,
+    dropping: Query<(), With<DropThrough>>,
+) {
+    for (player, input) in &players {
+        let pressed = input.pressed(&PlayerAction::Down);
+        let dropping = dropping.contains(player);
+        if pressed && !dropping {
+            commands.entity(player).insert(DropThrough);
+        } else if !pressed && dropping {
+            commands.entity(player).remove::<DropThrough>();
+        }
+    }
+}
+
+/// Based on the avian one way platform example, ignores any contact
+/// whose normal is not pointing up out of the platform
+fn one_way_platforms(
+    mut platforms: Query<&mut OneWayPlatform>,
+    dropping: Query<(), With<DropThrough>>,
+    mut collisions: ResMut<Collisions>,
+) {
+    collisions.retain(|contacts| {
+        let (mut platform, other, flip) = if let Ok(platform) = platforms.get_mut(contacts.entity1)
+        {
+            (platform, contacts.entity2, false)
+        } else if let Ok(platform) = platforms.get_mut(contacts.entity2) {
+            (platform, contacts.entity1, true)
+        } else {
+            return true;
+        };
+
+        if platform.0.contains(&other) {
+            let penetrating = contacts
+                .manifolds
+                .iter()
+                .any(|manifold| manifold.contacts.iter().any(|c| c.penetration > 0.));
+            if penetrating {
+                return false;
+            }
+            platform.0.remove(&other);
+        }
+
+        if dropping.contains(other) {
+            platform.0.insert(other);
+            return false;
+        }
+
+        let from_above = contacts.manifolds.iter().all(|manifold| {
+            let normal = if flip {
+                manifold.normal2
+            } else {
+                manifold.normal1
+            };
+            normal.length() > f32::EPSILON && normal.dot(Vec2::Y) >= 0.5
+        });
+        if !from_above {
+            platform.0.insert(other);
+        }
+        from_above
+    });
+}
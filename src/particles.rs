@@ -0,0 +1,204 @@
+//! Small sprites from the atlas that fly out, fall and fade, for dust, smoke, flashes
+//! and steam. They are only for show, so none of this uses the `GameRng`
+
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::{
+    game_state::{despawn_all, StartRun},
+    map::TileSprite,
+};
+
+pub fn plugin(app: &mut App) {
+    app.add_event::<Burst>()
+        .add_systems(StartRun, despawn_all::<Particle>)
+        .add_systems(Update, (emit, spawn_bursts, update_particles).chain());
+}
+
+/// In front of the player and the collision layer, behind the foreground
+const PARTICLE_Z: f32 = 5.;
+
+/// How one batch of particles looks and moves
+#[derive(Clone, Copy, Debug)]
+pub struct Emitter {
+    pub sprite: TileSprite,
+    pub count: usize,
+    pub size: f32,
+    pub color: Color,
+    /// in seconds, each particle lives somewhere between half of this and all of it
+    pub lifetime: f32,
+    pub velocity: Vec2,
+    /// the most each particle's velocity is moved at random on each axis
+    pub spread: Vec2,
+    pub gravity: f32,
+    /// fade out over the particle's life rather than vanish at the end
+    pub fade: bool,
+}
+
+/// Kicked up when a player lands
+pub const LANDING_DUST: Emitter = Emitter {
+    sprite: TileSprite::Smoke,
+    count: 4,
+    size: 8.,
+    color: Color::srgb(0.8, 0.7, 0.6),
+    lifetime: 0.4,
+    velocity: Vec2::new(0., 20.),
+    spread: Vec2::new(60., 10.),
+    gravity: 0.,
+    fade: true,
+};
+
+/// Out of the bottom of the gunboots with every shot
+pub const MUZZLE_FLASH: Emitter = Emitter {
+    sprite: TileSprite::Smoke,
+    count: 3,
+    size: 10.,
+    color: Color::srgb(1., 0.85, 0.4),
+    lifetime: 0.12,
+    velocity: Vec2::new(0., -120.),
+    spread: Vec2::new(40., 40.),
+    gravity: 0.,
+    fade: true,
+};
+
+/// What is left of a block when it breaks
+pub const DEBRIS: Emitter = Emitter {
+    sprite: TileSprite::Smoke,
+    count: 6,
+    size: 12.,
+    color: Color::WHITE,
+    lifetime: 0.8,
+    velocity: Vec2::new(0., 100.),
+    spread: Vec2::new(80., 60.),
+    gravity: 400.,
+    fade: true,
+};
+
+/// Where a player was when they were hurt
+pub const KNOCKOUT: Emitter = Emitter {
+    sprite: TileSprite::Smoke,
+    count: 12,
+    size: 14.,
+    color: Color::srgb(1., 0.5, 0.5),
+    lifetime: 0.7,
+    velocity: Vec2::ZERO,
+    spread: Vec2::new(140., 140.),
+    gravity: 0.,
+    fade: true,
+};
+
+/// Puffs rising out of a steam vent
+pub const STEAM: Emitter = Emitter {
+    sprite: TileSprite::Steam,
+    count: 1,
+    size: 16.,
+    color: Color::WHITE,
+    lifetime: 1.2,
+    velocity: Vec2::new(0., 50.),
+    spread: Vec2::new(10., 15.),
+    gravity: -20.,
+    fade: true,
+};
+
+/// Send to throw out one batch of particles
+#[derive(Event, Clone, Copy, Debug)]
+pub struct Burst {
+    pub emitter: Emitter,
+    pub at: Vec2,
+}
+
+/// Keeps sending out batches of particles from wherever it is
+#[derive(Component)]
+pub struct ParticleEmitter {
+    pub emitter: Emitter,
+    pub every: Timer,
+}
+
+impl ParticleEmitter {
+    pub fn new(emitter: Emitter, per_second: f32) -> Self {
+        ParticleEmitter {
+            emitter,
+            every: Timer::from_seconds(1. / per_second, TimerMode::Repeating),
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Particle {
+    velocity: Vec2,
+    gravity: f32,
+    fade: bool,
+    life: Timer,
+}
+
+fn spawn_particles(commands: &mut Commands, rng: &mut impl Rng, emitter: &Emitter, at: Vec2) {
+    for _ in 0..emitter.count {
+        let spread = Vec2::new(
+            rng.gen_range(-1. ..=1.) * emitter.spread.x,
+            rng.gen_range(-1. ..=1.) * emitter.spread.y,
+        );
+        let lifetime = rng.gen_range(0.5..=1.) * emitter.lifetime;
+        commands.spawn((
+            Name::new("Particle"),
+            Particle {
+                velocity: emitter.velocity + spread,
+                gravity: emitter.gravity,
+                fade: emitter.fade,
+                life: Timer::from_seconds(lifetime, TimerMode::Once),
+            },
+            SpriteBundle {
+                transform: Transform::from_translation(at.extend(PARTICLE_Z)),
+                sprite: Sprite {
+                    custom_size: Some(Vec2::splat(emitter.size)),
+                    color: emitter.color,
+                    ..default()
+                },
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            TextureAtlas::default(),
+            emitter.sprite,
+        ));
+    }
+}
+
+fn emit(
+    mut commands: Commands,
+    mut emitters: Query<(&mut ParticleEmitter, &GlobalTransform)>,
+    time: Res<Time>,
+) {
+    let mut rng = rand::thread_rng();
+    for (mut emitter, transform) in &mut emitters {
+        emitter.every.tick(time.delta());
+        for _ in 0..emitter.every.times_finished_this_tick() {
+            let at = transform.translation().truncate();
+            spawn_particles(&mut commands, &mut rng, &emitter.emitter, at);
+        }
+    }
+}
+
+fn spawn_bursts(mut commands: Commands, mut bursts: EventReader<Burst>) {
+    let mut rng = rand::thread_rng();
+    for burst in bursts.read() {
+        spawn_particles(&mut commands, &mut rng, &burst.emitter, burst.at);
+    }
+}
+
+fn update_particles(
+    mut commands: Commands,
+    mut particles: Query<(Entity, &mut Particle, &mut Transform, &mut Sprite)>,
+    time: Res<Time>,
+) {
+    let dt = time.delta_seconds();
+    for (entity, mut particle, mut transform, mut sprite) in &mut particles {
+        if particle.life.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        particle.velocity.y -= particle.gravity * dt;
+        transform.translation += (particle.velocity * dt).extend(0.);
+        if particle.fade {
+            sprite.color.set_alpha(particle.life.fraction_remaining());
+        }
+    }
+}
@@ -1,100 +1,382 @@
 use avian2d::prelude::*;
-use bevy::prelude::*;
+use bevy::{
+    input::gamepad::{GamepadConnection, GamepadConnectionEvent},
+    prelude::*,
+};
 use leafwing_input_manager::prelude::*;
 
-use crate::camera::MainCamera;
+use crate::{
+    audio::Sound,
+    combo::Combo,
+    controls::Controls,
+    game_state::{StartRun, StartSection},
+    gunboots::{Gunboots, Trigger},
+    kill_zone::SpawnPoint,
+    map::{Gameplay, Team},
+    particles::{Burst, LANDING_DUST},
+    tuning::{PlayerTuning, Tuning},
+};
 
 pub fn plugin(app: &mut App) {
-    app.add_systems(Startup, spawn_player)
-        .add_systems(PostStartup, add_camera_joint)
-        .add_systems(Update, (player_move, clamp_max_velocity));
+    app.register_type::<PlayerStats>()
+        .add_systems(Startup, spawn_first_player)
+        .add_systems(
+            Update,
+            ((claim_device, join_players).chain(), leave_players),
+        )
+        .add_systems(StartRun, respawn_players)
+        .add_systems(StartSection, return_to_spawn)
+        .add_systems(
+            FixedUpdate,
+            (update_grounded, climb, player_move, clamp_max_velocity)
+                .chain()
+                .in_set(Gameplay),
+        )
+        .add_systems(Update, kick_up_dust);
 }
 
+/// Which player this is, 0 is player one
 #[derive(Component)]
-pub struct Player;
+pub struct Player(pub usize);
 
-fn add_camera_joint(
+pub const MAX_PLAYERS: usize = 4;
+
+/// Where a player's input comes from, each gamepad can only belong to one player
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PlayerDevice {
+    Keyboard,
+    Gamepad(Gamepad),
+    /// the keyboard and every gamepad, until one of them is pressed and claims the player
+    Any,
+}
+
+/// Lost by touching a `KillZone`, the player is out of the run when it hits 0
+#[derive(Component)]
+pub struct Health {
+    pub current: u8,
+    pub max: u8,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Health {
+            current: PLAYER_HEALTH,
+            max: PLAYER_HEALTH,
+        }
+    }
+}
+
+/// How a player moves, starts out as the `PlayerTuning` and upgrades change it over a run
+#[derive(Component, Reflect, Clone, Copy, Debug)]
+#[reflect(Component)]
+pub struct PlayerStats {
+    pub horizontal_speed: f32,
+    pub jump_speed: f32,
+    /// fastest a player can move up or down
+    pub speed_limit: f32,
+    pub climb_speed: f32,
+    /// how close coins have to be before they fly to the player, 0 for no magnet
+    pub magnet_radius: f32,
+}
+
+impl Default for PlayerStats {
+    fn default() -> Self {
+        PlayerTuning::default().stats()
+    }
+}
+
+/// Coins a player has picked up this run
+#[derive(Component, Default)]
+pub struct Wallet(pub u32);
+
+/// Added to a player while there is something solid right under their feet
+#[derive(Component)]
+pub struct Grounded;
+
+/// Players overlapping this can move up and down it instead of falling
+#[derive(Component)]
+pub struct Climbable;
+
+/// Added to a player while they are holding on to something `Climbable`
+#[derive(Component)]
+pub struct Climbing;
+
+fn spawn_first_player(mut commands: Commands, controls: Res<Controls>) {
+    spawn_player(&mut commands, 0, PlayerDevice::Any, &controls);
+}
+
+/// Everyone still around starts the new run fresh, if nobody is then whoever presses first plays
+fn respawn_players(
+    mut commands: Commands,
+    players: Query<(Entity, &Player, &PlayerDevice)>,
+    controls: Res<Controls>,
+) {
+    let mut devices: Vec<_> = players
+        .iter()
+        .map(|(entity, player, device)| {
+            commands.entity(entity).despawn_recursive();
+            (player.0, *device)
+        })
+        .collect();
+    if devices.is_empty() {
+        devices.push((0, PlayerDevice::Any));
+    }
+    for (index, device) in devices {
+        spawn_player(&mut commands, index, device, &controls);
+    }
+}
+
+/// Each section starts from the top
+fn return_to_spawn(mut players: Query<(&SpawnPoint, &mut Transform, &mut LinearVelocity)>) {
+    for (spawn, mut transform, mut velocity) in &mut players {
+        transform.translation = spawn.0;
+        velocity.0 = Vec2::ZERO;
+    }
+}
+
+/// The first key or button pressed decides which device a `PlayerDevice::Any`
+/// player is on, after that other gamepads can join
+fn claim_device(
+    keys: Res<ButtonInput<KeyCode>>,
+    buttons: Res<ButtonInput<GamepadButton>>,
+    mut players: Query<(&mut PlayerDevice, &mut InputMap<PlayerAction>)>,
+    controls: Res<Controls>,
+) {
+    let claimed = if keys.get_just_pressed().next().is_some() {
+        PlayerDevice::Keyboard
+    } else if let Some(button) = buttons.get_just_pressed().next() {
+        PlayerDevice::Gamepad(button.gamepad)
+    } else {
+        return;
+    };
+    for (mut device, mut map) in &mut players {
+        if *device == PlayerDevice::Any {
+            *device = claimed;
+            *map = controls.input_map(claimed);
+        }
+    }
+}
+
+/// Pressing south on a gamepad that is not being used adds a player for it
+fn join_players(
+    mut commands: Commands,
+    buttons: Res<ButtonInput<GamepadButton>>,
+    players: Query<(&Player, &PlayerDevice)>,
+    controls: Res<Controls>,
+) {
+    for button in buttons.get_just_pressed() {
+        if button.button_type != GamepadButtonType::South {
+            continue;
+        }
+        let device = PlayerDevice::Gamepad(button.gamepad);
+        if players.iter().any(|(_, used)| *used == device) {
+            continue;
+        }
+        let Some(index) =
+            (0..MAX_PLAYERS).find(|&i| players.iter().all(|(player, _)| player.0 != i))
+        else {
+            continue;
+        };
+        spawn_player(&mut commands, index, device, &controls);
+    }
+}
+
+/// Players go away when their gamepad is unplugged
+fn leave_players(
     mut commands: Commands,
-    player: Query<Entity, With<Player>>,
-    camera: Query<Entity, With<MainCamera>>,
+    mut events: EventReader<GamepadConnectionEvent>,
+    players: Query<(Entity, &PlayerDevice)>,
 ) {
-    let player = player.single();
-    let camera = camera.single();
+    for event in events.read() {
+        if event.connection != GamepadConnection::Disconnected {
+            continue;
+        }
+        for (player, device) in &players {
+            if *device == PlayerDevice::Gamepad(event.gamepad) {
+                commands.entity(player).despawn_recursive();
+            }
+        }
+    }
+}
+
+pub(crate) fn spawn_player(
+    commands: &mut Commands,
+    index: usize,
+    device: PlayerDevice,
+    controls: &Controls,
+) -> Entity {
+    // every other player is on the pink team and spawns to the right
+    let (team, color, side) = if index.is_multiple_of(2) {
+        (Team::Yellow, Color::srgb(1., 0.9, 0.4), -1.)
+    } else {
+        (Team::Pink, Color::srgb(1., 0.6, 0.8), 1.)
+    };
+    let spawn = PLAYER_SPAWN + Vec3::X * side * PLAYER_SPACING * index.div_ceil(2) as f32;
     commands
-        .entity(player)
-        .insert(DistanceJoint::new(player, camera));
-}
-
-fn spawn_player(mut commands: Commands) {
-    commands.spawn((
-        Name::new("Player"),
-        Player,
-        RigidBody::Static,
-        LockedAxes::new().lock_rotation(),
-        InputManagerBundle {
-            action_state: ActionState::default(),
-            input_map: default_keybindings(),
-        },
-        SpriteBundle {
-            sprite: Sprite {
-                custom_size: Some(Vec2::splat(32.)),
+        .spawn((
+            Name::new(format!("Player {}", index + 1)),
+            Player(index),
+            device,
+            team,
+            RigidBody::Dynamic,
+            LockedAxes::new().lock_rotation(),
+            InputManagerBundle {
+                action_state: ActionState::default(),
+                input_map: controls.input_map(device),
+            },
+            SpriteBundle {
+                transform: Transform::from_translation(spawn),
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(Vec2::splat(32.)),
+                    ..default()
+                },
                 ..default()
             },
-            ..default()
-        },
-        Collider::rectangle(32., 32.),
-        Friction {
-            static_coefficient: 0.,
-            combine_rule: CoefficientCombine::Min,
-            ..default()
-        },
-    ));
-}
-
-#[derive(Actionlike, Reflect, Clone, Copy, Hash, PartialEq, Eq, Debug)]
+            Collider::rectangle(32., 32.),
+            ShapeCaster::new(Collider::rectangle(30., 30.), Vec2::ZERO, 0., Dir2::NEG_Y)
+                .with_max_time_of_impact(2.)
+                .with_ignore_origin_penetration(true),
+            (
+                Gunboots::default(),
+                Trigger::default(),
+                Health::default(),
+                Wallet::default(),
+                Combo::default(),
+                PlayerStats::default(),
+                SpawnPoint(spawn),
+            ),
+            CollidingEntities::default(),
+            GravityScale(1.),
+            Friction {
+                static_coefficient: 0.,
+                combine_rule: CoefficientCombine::Min,
+                ..default()
+            },
+        ))
+        .id()
+}
+
+#[derive(
+    Actionlike,
+    Reflect,
+    Clone,
+    Copy,
+    Hash,
+    PartialEq,
+    Eq,
+    Debug,
+    strum_macros::EnumIter,
+    serde::Serialize,
+    serde::Deserialize,
+)]
 pub enum PlayerAction {
     MoveLeft,
     MoveRight,
     Jump,
+    Down,
 }
 
-fn default_keybindings() -> InputMap<PlayerAction> {
-    InputMap::new([
-        (PlayerAction::MoveLeft, KeyCode::KeyA),
-        (PlayerAction::MoveRight, KeyCode::KeyD),
-        (PlayerAction::Jump, KeyCode::KeyW),
-        (PlayerAction::MoveLeft, KeyCode::ArrowLeft),
-        (PlayerAction::MoveRight, KeyCode::ArrowRight),
-        (PlayerAction::Jump, KeyCode::ArrowUp),
-    ])
+/// just above the top of the lobby
+const PLAYER_SPAWN: Vec3 = Vec3::new(0., 320., 0.);
+/// how far apart players spawn
+const PLAYER_SPACING: f32 = 48.;
+const PLAYER_HEALTH: u8 = 4;
+
+pub(crate) fn update_grounded(
+    mut commands: Commands,
+    players: Query<(Entity, &ShapeHits), With<Player>>,
+    sensors: Query<(), With<Sensor>>,
+) {
+    for (player, hits) in &players {
+        if hits.iter().any(|hit| !sensors.contains(hit.entity)) {
+            commands.entity(player).insert(Grounded);
+        } else {
+            commands.entity(player).remove::<Grounded>();
+        }
+    }
+}
+
+/// A puff of dust under the player's feet whenever they land
+fn kick_up_dust(
+    players: Query<&Transform, (With<Player>, Added<Grounded>)>,
+    mut bursts: EventWriter<Burst>,
+) {
+    for transform in &players {
+        bursts.send(Burst {
+            emitter: LANDING_DUST,
+            at: transform.translation.truncate() - Vec2::Y * 14.,
+        });
+    }
 }
 
-const PLAYER_HORIZONTAL_SPEED: f32 = 100.;
-const PLAYER_JUMP_SPEED: f32 = 250.;
-const PLAYER_SPEED_LIMIT: f32 = 250.;
+type Climber = (
+    Entity,
+    &'static ActionState<PlayerAction>,
+    &'static CollidingEntities,
+    &'static mut LinearVelocity,
+    &'static mut GravityScale,
+    &'static PlayerStats,
+    Has<Climbing>,
+);
+
+/// Pressing up while touching something `Climbable` grabs on to it,
+/// gravity is turned off until the player moves off of it
+fn climb(
+    mut commands: Commands,
+    mut players: Query<Climber, With<Player>>,
+    climbable: Query<(), With<Climbable>>,
+) {
+    for (player, input, colliding, mut velocity, mut gravity, stats, climbing) in &mut players {
+        let touching = colliding.iter().any(|entity| climbable.contains(*entity));
+        let up = input.pressed(&PlayerAction::Jump);
+        let down = input.pressed(&PlayerAction::Down);
+        if touching && (climbing || up) {
+            if !climbing {
+                commands.entity(player).insert(Climbing);
+            }
+            gravity.0 = 0.;
+            velocity.y = (up as i8 - down as i8) as f32 * stats.climb_speed;
+        } else if climbing {
+            commands.entity(player).remove::<Climbing>();
+            gravity.0 = 1.;
+        }
+    }
+}
+
+type Mover = (
+    &'static ActionState<PlayerAction>,
+    &'static mut LinearVelocity,
+    &'static PlayerStats,
+    Has<Grounded>,
+    Has<Climbing>,
+);
 
 fn player_move(
-    mut players: Query<(&ActionState<PlayerAction>, &mut LinearVelocity)>,
+    mut players: Query<Mover>,
+    tuning: Res<Tuning>,
     time: Res<Time>,
+    mut sounds: EventWriter<Sound>,
 ) {
-    for (input, mut velocity) in &mut players {
+    for (input, mut velocity, stats, grounded, climbing) in &mut players {
         if input.pressed(&PlayerAction::MoveLeft) {
-            velocity.x = -PLAYER_HORIZONTAL_SPEED;
+            velocity.x = -stats.horizontal_speed;
         } else if input.pressed(&PlayerAction::MoveRight) {
-            velocity.x = PLAYER_HORIZONTAL_SPEED;
+            velocity.x = stats.horizontal_speed;
         } else {
-            velocity.x = velocity.x.lerp(0., time.delta_seconds() * 4.);
+            velocity.x = velocity
+                .x
+                .lerp(0., time.delta_seconds() * tuning.current.friction);
         };
-        if input.just_pressed(&PlayerAction::Jump) {
-            velocity.0.y += PLAYER_JUMP_SPEED;
+        if grounded && !climbing && input.just_pressed(&PlayerAction::Jump) {
+            velocity.0.y += stats.jump_speed;
+            sounds.send(Sound::Jump);
         }
     }
 }
 
-fn clamp_max_velocity(
-    mut players: Query<&mut LinearVelocity, With<Player>>
-) {
-    for mut player in &mut players {
-        player.0.y = player.0.y.clamp(-PLAYER_SPEED_LIMIT, PLAYER_SPEED_LIMIT);
+fn clamp_max_velocity(mut players: Query<(&mut LinearVelocity, &PlayerStats)>) {
+    for (mut velocity, stats) in &mut players {
+        velocity.y = velocity.y.clamp(-stats.speed_limit, stats.speed_limit);
     }
 }
@@ -0,0 +1,296 @@
+//! A run is a string of well sections, clearing one means falling out the bottom of it.
+//! After a section each player picks an upgrade, and every few sections there is a shop
+//! room instead where upgrades are bought with coins
+
+use avian2d::prelude::*;
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::*;
+use rand::seq::IteratorRandom;
+use strum::IntoEnumIterator;
+
+use crate::{
+    game_state::{GameState, StartRun, StartSection},
+    gunboots::Gunboots,
+    map::{Gameplay, LoadMap, MapBounds},
+    player::{Grounded, Health, Player, PlayerAction, PlayerStats, Wallet},
+    seed::GameRng,
+};
+
+pub fn plugin(app: &mut App) {
+    app.register_type::<Upgrade>()
+        .init_resource::<Section>()
+        .init_resource::<UpgradeChoices>()
+        .add_event::<UpgradePicked>()
+        .add_event::<UpgradeBought>()
+        .add_systems(StartRun, restart_sections)
+        .add_systems(StartSection, next_section)
+        .add_systems(
+            FixedUpdate,
+            (buy_upgrades, clear_section).chain().in_set(Gameplay),
+        )
+        .add_systems(OnEnter(GameState::Upgrade), queue_pickers)
+        .add_systems(
+            Update,
+            (
+                apply_picks.run_if(in_state(GameState::Upgrade)),
+                label_shop_items,
+            ),
+        );
+}
+
+/// Every this many sections is a shop
+const SHOP_EVERY: u32 = 3;
+const SHOP_MAP: &str = "maps/shop.map";
+/// How many upgrades each player gets to pick from
+const UPGRADE_CHOICES: usize = 3;
+/// How far below the bottom tile of a section a player has to fall to clear it
+const CLEAR_DEPTH: f32 = 64.;
+
+#[derive(
+    Reflect,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Debug,
+    strum_macros::EnumIter,
+    strum_macros::EnumString,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[strum(serialize_all = "snake_case", ascii_case_insensitive)]
+pub enum Upgrade {
+    MaxAmmo,
+    JumpHigher,
+    Magnet,
+    ExtraHealth,
+}
+
+impl Upgrade {
+    pub fn name(self) -> &'static str {
+        match self {
+            Upgrade::MaxAmmo => "+2 max ammo",
+            Upgrade::JumpHigher => "Jump higher",
+            Upgrade::Magnet => "Coin magnet",
+            Upgrade::ExtraHealth => "+1 max health",
+        }
+    }
+
+    /// Short enough to fit over a shop item
+    fn short_name(self) -> &'static str {
+        match self {
+            Upgrade::MaxAmmo => "Ammo",
+            Upgrade::JumpHigher => "Jump",
+            Upgrade::Magnet => "Magnet",
+            Upgrade::ExtraHealth => "Health",
+        }
+    }
+
+    fn apply(self, stats: &mut PlayerStats, gunboots: &mut Gunboots, health: &mut Health) {
+        match self {
+            Upgrade::MaxAmmo => {
+                gunboots.max_ammo = gunboots.max_ammo.saturating_add(2);
+                gunboots.ammo = gunboots.max_ammo;
+            }
+            Upgrade::JumpHigher => stats.jump_speed += 30.,
+            Upgrade::Magnet => stats.magnet_radius += 64.,
+            Upgrade::ExtraHealth => {
+                health.max = health.max.saturating_add(1);
+                health.current = health.current.saturating_add(1).min(health.max);
+            }
+        }
+    }
+}
+
+/// Stand on this tile and press down to buy `upgrade`
+#[derive(Component)]
+pub struct ShopItem {
+    pub upgrade: Upgrade,
+    pub price: u32,
+}
+
+/// Which section of the run the players are on
+#[derive(Resource)]
+pub struct Section {
+    /// counting from 1, shops included
+    pub number: u32,
+    /// the map the run is played on, put aside while in a shop
+    well: Option<String>,
+}
+
+impl Default for Section {
+    fn default() -> Self {
+        Section {
+            number: 1,
+            well: None,
+        }
+    }
+}
+
+impl Section {
+    pub fn in_shop(&self) -> bool {
+        self.well.is_some()
+    }
+}
+
+/// The player picking an upgrade and what they get to pick from
+#[derive(Resource, Default)]
+pub struct UpgradeChoices {
+    pub picker: Option<(Entity, usize)>,
+    pub offers: Vec<Upgrade>,
+    /// players still waiting to pick, the last one picks next
+    queue: Vec<(Entity, usize)>,
+}
+
+/// Send to give the current picker an upgrade
+#[derive(Event, Clone, Copy, Debug)]
+pub struct UpgradePicked(pub Upgrade);
+
+/// Sent when someone buys an upgrade in a shop
+#[derive(Event, Clone, Copy, Debug)]
+pub struct UpgradeBought(pub Upgrade);
+
+fn restart_sections(mut section: ResMut<Section>, mut load: ResMut<LoadMap>) {
+    if let Some(well) = section.well.take() {
+        load.0 = well;
+    }
+    section.number = 0;
+}
+
+/// Spawn the next section fresh, or the shop if it is time for one
+fn next_section(mut section: ResMut<Section>, mut load: ResMut<LoadMap>) {
+    section.number += 1;
+    if let Some(well) = section.well.take() {
+        load.0 = well;
+    } else if section.number.is_multiple_of(SHOP_EVERY) {
+        section.well = Some(std::mem::replace(&mut load.0, SHOP_MAP.to_string()));
+    } else {
+        load.set_changed();
+    }
+}
+
+fn clear_section(
+    mut commands: Commands,
+    players: Query<&Transform, With<Player>>,
+    bounds: Query<&MapBounds>,
+    section: Res<Section>,
+    mut next: ResMut<NextState<GameState>>,
+) {
+    let Ok(bounds) = bounds.get_single() else {
+        return;
+    };
+    let bottom = bounds.0.min.y - CLEAR_DEPTH;
+    if !players
+        .iter()
+        .any(|transform| transform.translation.y < bottom)
+    {
+        return;
+    }
+    if section.in_shop() {
+        // nothing to pick after a shop, straight on to the next section
+        commands.add(|world: &mut World| world.run_schedule(StartSection));
+    } else {
+        next.set(GameState::Upgrade);
+    }
+}
+
+type Buyer = (
+    &'static ActionState<PlayerAction>,
+    &'static ShapeHits,
+    &'static mut Wallet,
+    &'static mut PlayerStats,
+    &'static mut Gunboots,
+    &'static mut Health,
+);
+
+fn buy_upgrades(
+    mut commands: Commands,
+    mut players: Query<Buyer, (With<Player>, With<Grounded>)>,
+    items: Query<&ShopItem>,
+    mut bought: EventWriter<UpgradeBought>,
+) {
+    for (input, hits, mut wallet, mut stats, mut gunboots, mut health) in &mut players {
+        if !input.just_pressed(&PlayerAction::Down) {
+            continue;
+        }
+        for hit in hits.iter() {
+            let Ok(item) = items.get(hit.entity) else {
+                continue;
+            };
+            if wallet.0 < item.price {
+                continue;
+            }
+            wallet.0 -= item.price;
+            item.upgrade.apply(&mut stats, &mut gunboots, &mut health);
+            bought.send(UpgradeBought(item.upgrade));
+            commands
+                .entity(hit.entity)
+                .remove::<ShopItem>()
+                .despawn_descendants();
+            break;
+        }
+    }
+}
+
+fn label_shop_items(mut commands: Commands, items: Query<(Entity, &ShopItem), Added<ShopItem>>) {
+    for (entity, item) in &items {
+        commands.entity(entity).with_children(|tile| {
+            tile.spawn(Text2dBundle {
+                text: Text::from_section(
+                    format!("{}\n{}", item.upgrade.short_name(), item.price),
+                    TextStyle {
+                        font_size: 32.,
+                        ..default()
+                    },
+                )
+                .with_justify(JustifyText::Center),
+                transform: Transform::from_xyz(0., 48., 1.),
+                ..default()
+            });
+        });
+    }
+}
+
+/// Everyone picks in player order, starting with player one
+fn queue_pickers(
+    mut choices: ResMut<UpgradeChoices>,
+    players: Query<(Entity, &Player)>,
+    mut rng: ResMut<GameRng>,
+) {
+    let mut queue: Vec<_> = players
+        .iter()
+        .map(|(entity, player)| (entity, player.0))
+        .collect();
+    queue.sort_by_key(|(_, index)| std::cmp::Reverse(*index));
+    choices.queue = queue;
+    next_picker(&mut choices, &mut rng);
+}
+
+fn next_picker(choices: &mut UpgradeChoices, rng: &mut GameRng) {
+    choices.picker = choices.queue.pop();
+    choices.offers = match choices.picker {
+        Some(_) => Upgrade::iter().choose_multiple(&mut rng.0, UPGRADE_CHOICES),
+        None => Vec::new(),
+    };
+}
+
+pub(crate) fn apply_picks(
+    mut picks: EventReader<UpgradePicked>,
+    mut choices: ResMut<UpgradeChoices>,
+    mut players: Query<(&mut PlayerStats, &mut Gunboots, &mut Health)>,
+    mut rng: ResMut<GameRng>,
+    mut next: ResMut<NextState<GameState>>,
+) {
+    for UpgradePicked(upgrade) in picks.read() {
+        let Some((picker, _)) = choices.picker else {
+            continue;
+        };
+        if let Ok((mut stats, mut gunboots, mut health)) = players.get_mut(picker) {
+            upgrade.apply(&mut stats, &mut gunboots, &mut health);
+        }
+        next_picker(&mut choices, &mut rng);
+        if choices.picker.is_none() {
+            next.set(GameState::InGame);
+        }
+    }
+}
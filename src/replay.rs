@@ -0,0 +1,228 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::{app::AppExit, prelude::*};
+use leafwing_input_manager::prelude::*;
+use strum::IntoEnumIterator;
+
+use crate::{
+    controls::Controls,
+    game_state::{skip_title, GameState, StartRun},
+    map::{Gameplay, LoadMap},
+    player::{spawn_player, Player, PlayerAction, PlayerDevice},
+    progression::{apply_picks, Upgrade, UpgradePicked},
+    save::{write_atomic, SaveDir},
+    seed::{Seed, SeedSource},
+};
+
+pub fn plugin(app: &mut App) {
+    let mode = match std::env::var_os("WOWNDELL_REPLAY") {
+        Some(path) => match Replay::load(&PathBuf::from(path)) {
+            Ok(replay) => {
+                app.insert_resource(Seed(replay.seed))
+                    .insert_resource(SeedSource {
+                        fixed: Some(Seed(replay.seed)),
+                        daily: false,
+                        again: false,
+                    })
+                    .insert_resource(LoadMap(replay.map.clone()))
+                    .add_systems(Startup, skip_title);
+                ReplayMode::Playback { replay, tick: 0 }
+            }
+            Err(e) => {
+                error!("{e}");
+                ReplayMode::Record(Replay::default())
+            }
+        },
+        None => ReplayMode::Record(Replay::default()),
+    };
+    app.insert_resource(mode)
+        .add_systems(FixedPreUpdate, (record, play_back).chain().in_set(Gameplay))
+        .add_systems(StartRun, restart_recording)
+        .add_systems(
+            Update,
+            (
+                record_picks,
+                play_back_picks
+                    .before(apply_picks)
+                    .run_if(in_state(GameState::Upgrade)),
+            ),
+        )
+        .add_systems(Update, save_on_key)
+        .add_systems(Last, save_on_exit);
+}
+
+/// Every player's held actions for each fixed tick of a run, along with what is
+/// needed to start the run again the same way
+#[derive(serde::Deserialize, serde::Serialize, Default, Debug)]
+pub struct Replay {
+    pub map: String,
+    pub seed: u64,
+    /// which actions each player was holding, by player number
+    pub ticks: Vec<Vec<(usize, Vec<PlayerAction>)>>,
+    /// upgrades picked between sections, in the order they were picked
+    #[serde(default)]
+    pub upgrades: Vec<Upgrade>,
+}
+
+impl Replay {
+    pub fn load(path: &PathBuf) -> Result<Replay, String> {
+        let data = fs::read_to_string(path).map_err(|e| format!("{:?}: {e}", path))?;
+        ron::from_str(&data).map_err(|e| format!("{:?}: {e}", path))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let data = ron::ser::to_string(self).map_err(|e| e.to_string())?;
+        write_atomic(path, data.as_bytes()).map_err(|e| format!("{:?}: {e}", path))
+    }
+}
+
+/// Set `WOWNDELL_REPLAY` to a replay file to watch it, otherwise the run is recorded
+#[derive(Resource)]
+pub enum ReplayMode {
+    Record(Replay),
+    Playback { replay: Replay, tick: usize },
+}
+
+/// Where F9 and quitting save the recording, in the `SaveDir`
+const REPLAY_FILE: &str = "replays/last.replay";
+
+/// Each run is recorded on its own, the last one is what gets saved
+fn restart_recording(mut mode: ResMut<ReplayMode>) {
+    if let ReplayMode::Record(replay) = mode.as_mut() {
+        *replay = Replay::default();
+    }
+}
+
+fn record(
+    mut mode: ResMut<ReplayMode>,
+    players: Query<(&Player, &ActionState<PlayerAction>)>,
+    map: Res<LoadMap>,
+    seed: Res<Seed>,
+) {
+    let ReplayMode::Record(replay) = mode.as_mut() else {
+        return;
+    };
+    if replay.ticks.is_empty() {
+        replay.map = map.0.clone();
+        replay.seed = seed.0;
+    }
+    let mut tick: Vec<_> = players
+        .iter()
+        .map(|(player, input)| {
+            let held = PlayerAction::iter()
+                .filter(|action| input.pressed(action))
+                .collect();
+            (player.0, held)
+        })
+        .collect();
+    tick.sort_by_key(|(player, _)| *player);
+    replay.ticks.push(tick);
+}
+
+/// Upgrade picks happen on menus between ticks so they are kept apart from the input
+fn record_picks(mut mode: ResMut<ReplayMode>, mut picks: EventReader<UpgradePicked>) {
+    let ReplayMode::Record(replay) = mode.as_mut() else {
+        picks.clear();
+        return;
+    };
+    replay
+        .upgrades
+        .extend(picks.read().map(|UpgradePicked(upgrade)| *upgrade));
+}
+
+/// One pick a frame, whoever is picking next gets the next upgrade in the recording
+fn play_back_picks(mut mode: ResMut<ReplayMode>, mut picks: EventWriter<UpgradePicked>) {
+    let ReplayMode::Playback { replay, .. } = mode.as_mut() else {
+        return;
+    };
+    if !replay.upgrades.is_empty() {
+        picks.send(UpgradePicked(replay.upgrades.remove(0)));
+    }
+}
+
+type Replayed = (
+    Entity,
+    &'static Player,
+    &'static mut ActionState<PlayerAction>,
+    &'static mut InputMap<PlayerAction>,
+);
+
+/// Players are added and removed to match the recording, their bindings are
+/// cleared so only the recording moves them
+fn play_back(
+    mut commands: Commands,
+    mut mode: ResMut<ReplayMode>,
+    mut players: Query<Replayed>,
+    controls: Res<Controls>,
+) {
+    let ReplayMode::Playback { replay, tick } = mode.as_mut() else {
+        return;
+    };
+    let Some(held) = replay.ticks.get(*tick) else {
+        if *tick == replay.ticks.len() {
+            info!("replay finished after {tick} ticks");
+            *tick += 1;
+        }
+        return;
+    };
+    *tick += 1;
+
+    for (entity, player, mut input, mut map) in &mut players {
+        let Some((_, actions)) = held.iter().find(|(index, _)| *index == player.0) else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+        if !map.is_empty() {
+            *map = InputMap::default();
+        }
+        for action in PlayerAction::iter() {
+            let pressed = actions.contains(&action);
+            if pressed && !input.pressed(&action) {
+                input.press(&action);
+            } else if !pressed && input.pressed(&action) {
+                input.release(&action);
+            }
+        }
+    }
+    for (index, actions) in held {
+        if players.iter().any(|(_, player, _, _)| player.0 == *index) {
+            continue;
+        }
+        let mut input = ActionState::default();
+        for action in actions {
+            input.press(action);
+        }
+        let player = spawn_player(&mut commands, *index, PlayerDevice::Keyboard, &controls);
+        commands
+            .entity(player)
+            .insert((input, InputMap::<PlayerAction>::default()));
+    }
+}
+
+fn save(mode: &ReplayMode, dir: &SaveDir) {
+    let ReplayMode::Record(replay) = mode else {
+        return;
+    };
+    let Some(path) = dir.file(REPLAY_FILE) else {
+        return;
+    };
+    match replay.save(&path) {
+        Ok(()) => info!("saved replay to {:?}", path),
+        Err(e) => error!("{e}"),
+    }
+}
+
+fn save_on_key(mode: Res<ReplayMode>, dir: Res<SaveDir>, keys: Res<ButtonInput<KeyCode>>) {
+    if keys.just_pressed(KeyCode::F9) {
+        save(&mode, &dir);
+    }
+}
+
+fn save_on_exit(mode: Res<ReplayMode>, dir: Res<SaveDir>, mut exit: EventReader<AppExit>) {
+    if exit.read().next().is_some() {
+        save(&mode, &dir);
+    }
+}
@@ -0,0 +1,318 @@
+//! The player's profile, kept between sessions in `profile.ron` in the `SaveDir`
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use bevy::{app::AppExit, prelude::*};
+
+use crate::{
+    audio::AudioMix,
+    camera::CameraFeel,
+    game_state::{GameState, RunStats},
+    kill_zone::PlayerDied,
+    progression::{Upgrade, UpgradeBought, UpgradePicked},
+};
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<SaveDir>();
+    let profile = app.world().resource::<SaveDir>().load();
+    app.insert_resource(profile)
+        .add_systems(OnEnter(GameState::GameOver), record_run)
+        .add_systems(
+            OnTransition {
+                exited: GameState::Paused,
+                entered: GameState::Title,
+            },
+            record_run,
+        )
+        .add_systems(
+            Update,
+            (
+                (unlock_upgrades, count_deaths),
+                apply_settings.run_if(resource_changed::<Profile>),
+            )
+                .chain(),
+        )
+        .add_systems(Last, save_on_exit);
+}
+
+/// Bumped whenever a change to `Profile` would read wrong as the old layout.
+/// Added fields do not need a bump since missing fields load as their defaults
+const SAVE_VERSION: u32 = 1;
+
+/// Where everything kept between sessions is saved, `None` keeps it all in memory only
+#[derive(Resource)]
+pub struct SaveDir(pub Option<PathBuf>);
+
+impl Default for SaveDir {
+    fn default() -> Self {
+        SaveDir(dirs::data_dir().map(|dir| dir.join("wowndell")))
+    }
+}
+
+impl SaveDir {
+    pub fn file(&self, name: &str) -> Option<PathBuf> {
+        self.0.as_ref().map(|dir| dir.join(name))
+    }
+}
+
+/// Everything kept from one session to the next
+#[derive(Resource, serde::Deserialize, serde::Serialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct Profile {
+    pub version: u32,
+    /// every upgrade that has ever been picked or bought
+    pub unlocked: Vec<Upgrade>,
+    /// coins picked up over every run
+    pub total_coins: u64,
+    /// in tiles
+    pub best_depth: u32,
+    pub runs: u32,
+    /// how many times players have been knocked out of a run, by what did it
+    pub deaths: BTreeMap<String, u32>,
+    pub settings: Settings,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile {
+            version: SAVE_VERSION,
+            unlocked: Vec::new(),
+            total_coins: 0,
+            best_depth: 0,
+            runs: 0,
+            deaths: BTreeMap::new(),
+            settings: Settings::default(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct Settings {
+    /// scales how hard the camera shakes, 0 turns it off
+    pub screen_shake: f32,
+    /// pause for a moment on big hits
+    pub freeze_frames: bool,
+    /// from 0 to 1, scales the music and effect volumes
+    pub volume: f32,
+    pub music_volume: f32,
+    pub effects_volume: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            screen_shake: 1.,
+            freeze_frames: true,
+            volume: 1.,
+            music_volume: 0.7,
+            effects_volume: 1.,
+        }
+    }
+}
+
+/// Saves from before there was a version number
+#[derive(serde::Deserialize, Default)]
+#[serde(default)]
+struct ProfileV0 {
+    unlocked: Vec<Upgrade>,
+    total_coins: u64,
+    best_depth: u32,
+    runs: u32,
+    deaths: BTreeMap<String, u32>,
+    settings: Settings,
+}
+
+impl From<ProfileV0> for Profile {
+    fn from(old: ProfileV0) -> Self {
+        Profile {
+            version: SAVE_VERSION,
+            unlocked: old.unlocked,
+            total_coins: old.total_coins,
+            best_depth: old.best_depth,
+            runs: old.runs,
+            deaths: old.deaths,
+            settings: old.settings,
+        }
+    }
+}
+
+/// Just enough of a save to know how to read the rest of it
+#[derive(serde::Deserialize)]
+struct Versioned {
+    #[serde(default)]
+    version: u32,
+}
+
+impl Profile {
+    /// Read a saved profile, bringing older layouts up to date
+    fn parse(data: &str) -> Result<Profile, String> {
+        let Versioned { version } = ron::from_str(data).map_err(|e| e.to_string())?;
+        match version {
+            SAVE_VERSION => ron::from_str(data).map_err(|e| e.to_string()),
+            // when the layout changes keep the old one around as `ProfileV{n}` and
+            // convert it into the new one here
+            0 => ron::from_str::<ProfileV0>(data)
+                .map(Profile::from)
+                .map_err(|e| e.to_string()),
+            newer if newer > SAVE_VERSION => Err(format!(
+                "saved by a newer version of the game ({newer}, this is {SAVE_VERSION})"
+            )),
+            older => Err(format!("no way to read save version {older}")),
+        }
+    }
+
+    /// Start fresh if there is no save yet or it can not be read
+    fn load(path: &Path) -> Profile {
+        load_or_move_aside(path, Profile::parse).unwrap_or_default()
+    }
+
+    fn save(&self, path: &Path) -> Result<(), String> {
+        let data = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())
+            .map_err(|e| e.to_string())?;
+        write_atomic(path, data.as_bytes()).map_err(|e| format!("{:?}: {e}", path))
+    }
+}
+
+const PROFILE_FILE: &str = "profile.ron";
+
+impl SaveDir {
+    fn load(&self) -> Profile {
+        match self.file(PROFILE_FILE) {
+            Some(path) => Profile::load(&path),
+            None => Profile::default(),
+        }
+    }
+}
+
+/// Read and `parse` the file at `path`, `None` if there is none. One that can not be
+/// parsed is moved aside to `.ron.bak` rather than left to be overwritten, so
+/// nothing is lost
+pub fn load_or_move_aside<T>(
+    path: &Path,
+    parse: impl FnOnce(&str) -> Result<T, String>,
+) -> Option<T> {
+    let data = fs::read_to_string(path).ok()?;
+    match parse(&data) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            error!("{:?}: {e}", path);
+            let backup = path.with_extension("ron.bak");
+            if let Err(e) = fs::rename(path, &backup) {
+                error!("failed to move {:?} aside: {e}", path);
+            }
+            None
+        }
+    }
+}
+
+/// Write to a file next to `path` and swap it in, so a crash part way through leaves
+/// either the old file or the new one and never half of each
+pub fn write_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temp = path.with_extension("tmp");
+    let mut file = fs::File::create(&temp)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs::rename(&temp, path)
+}
+
+fn save(profile: &Profile, dir: &SaveDir) {
+    let Some(path) = dir.file(PROFILE_FILE) else {
+        return;
+    };
+    if let Err(e) = profile.save(&path) {
+        error!("{e}");
+    }
+}
+
+/// Runs count once they are over, whether everyone died or they were quit
+fn record_run(mut profile: ResMut<Profile>, stats: Res<RunStats>, dir: Res<SaveDir>) {
+    profile.runs += 1;
+    profile.total_coins += stats.coins as u64;
+    profile.best_depth = profile.best_depth.max(stats.depth);
+    save(&profile, &dir);
+}
+
+fn unlock_upgrades(
+    mut profile: ResMut<Profile>,
+    mut picked: EventReader<UpgradePicked>,
+    mut bought: EventReader<UpgradeBought>,
+) {
+    let upgrades = picked
+        .read()
+        .map(|UpgradePicked(upgrade)| *upgrade)
+        .chain(bought.read().map(|UpgradeBought(upgrade)| *upgrade));
+    for upgrade in upgrades {
+        if !profile.unlocked.contains(&upgrade) {
+            profile.unlocked.push(upgrade);
+        }
+    }
+}
+
+fn count_deaths(mut profile: ResMut<Profile>, mut died: EventReader<PlayerDied>) {
+    for death in died.read() {
+        *profile.deaths.entry(death.cause.clone()).or_default() += 1;
+    }
+}
+
+fn apply_settings(profile: Res<Profile>, mut feel: ResMut<CameraFeel>, mut mix: ResMut<AudioMix>) {
+    let settings = &profile.settings;
+    feel.intensity = settings.screen_shake;
+    feel.freeze_frames = settings.freeze_frames;
+    mix.master = settings.volume.clamp(0., 1.);
+    mix.music = settings.music_volume.clamp(0., 1.);
+    mix.effects = settings.effects_volume.clamp(0., 1.);
+}
+
+fn save_on_exit(profile: Res<Profile>, dir: Res<SaveDir>, mut exit: EventReader<AppExit>) {
+    if exit.read().next().is_some() {
+        save(&profile, &dir);
+    }
+}
+
+#[test]
+fn missing_fields_load_as_defaults() {
+    let profile = Profile::parse("(version: 1, runs: 3, settings: (screen_shake: 0.5))").unwrap();
+    assert_eq!(profile.runs, 3);
+    assert_eq!(profile.settings.screen_shake, 0.5);
+    assert!(profile.settings.freeze_frames);
+    assert!(profile.deaths.is_empty());
+}
+
+#[test]
+fn unreadable_saves_are_errors() {
+    assert!(Profile::parse("(version: 99, runs: 3)").is_err());
+    assert!(Profile::parse("(runs: [3])").is_err());
+    assert!(Profile::parse("not a save").is_err());
+}
+
+#[test]
+fn versionless_saves_are_upgraded() {
+    let profile =
+        Profile::parse("(runs: 3, unlocked: [Magnet], settings: (screen_shake: 0.5))").unwrap();
+    assert_eq!(profile.version, SAVE_VERSION);
+    assert_eq!(profile.runs, 3);
+    assert_eq!(profile.unlocked, [Upgrade::Magnet]);
+    assert_eq!(profile.settings.screen_shake, 0.5);
+}
+
+#[test]
+fn saves_round_trip() {
+    let path = std::env::temp_dir().join(format!("wowndell-{}", std::process::id()));
+    let path = path.join("profile.ron");
+    let mut profile = Profile::default();
+    profile.unlocked.push(Upgrade::Magnet);
+    profile.deaths.insert("Void".to_string(), 2);
+    profile.save(&path).unwrap();
+    assert_eq!(Profile::load(&path), profile);
+    assert!(!path.with_extension("tmp").exists());
+    let _ = fs::remove_dir_all(path.parent().unwrap());
+}
@@ -0,0 +1,239 @@
+use std::{
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::game_state::StartRun;
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<Seed>()
+        .init_resource::<SeedSource>()
+        .init_resource::<GameRng>()
+        .add_systems(StartRun, (pick_seed, reseed).chain());
+}
+
+/// The seed for this run, everything random that changes how the game plays comes from it.
+/// Shown and shared as a short code, see `Seed::parse`
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Seed(pub u64);
+
+/// Random and daily seeds stay under this many bits so their codes are 8 letters long
+const SHORT_BITS: u32 = 40;
+/// Crockford's base 32, no I, L, O or U so codes are hard to misread
+const CODE_ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+impl Default for Seed {
+    fn default() -> Self {
+        Seed::random()
+    }
+}
+
+/// Kept apart so that, say, how tiles look never lines up with what gameplay rolls
+#[derive(Clone, Copy, Debug)]
+pub enum Stream {
+    Gameplay = 1,
+    Tiles,
+    /// for the well generator in `old_map.rs`, which is not built right now
+    #[allow(dead_code)]
+    Chunks,
+    Daily,
+    Phrase,
+}
+
+/// The splitmix64 finalizer, every bit of the input flips about half the output bits
+fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+impl Seed {
+    pub fn random() -> Seed {
+        Seed(rand::thread_rng().gen::<u64>() >> (64 - SHORT_BITS))
+    }
+
+    /// The same for everyone on the same UTC day
+    pub fn daily(days_since_epoch: u64) -> Seed {
+        Seed(Seed(days_since_epoch).derive(Stream::Daily, &[]) >> (64 - SHORT_BITS))
+    }
+
+    pub fn today() -> Seed {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Seed::daily(now.as_secs() / (24 * 60 * 60))
+    }
+
+    /// A number for one thing on one stream, like a chunk or a tile by its position
+    pub fn derive(self, stream: Stream, parts: &[i64]) -> u64 {
+        parts
+            .iter()
+            .fold(mix(self.0 ^ mix(stream as u64)), |hash, part| {
+                mix(hash ^ *part as u64)
+            })
+    }
+
+    pub fn rng(self, stream: Stream, parts: &[i64]) -> StdRng {
+        StdRng::seed_from_u64(self.derive(stream, parts))
+    }
+
+    /// Whole numbers are used as they are, codes like `K3F9-QX2A` are read back into the
+    /// seed they were made from, and anything else is a phrase that is hashed into a seed
+    pub fn parse(text: &str) -> Result<Seed, String> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err("a seed can not be empty".to_string());
+        }
+        if let Ok(number) = text.parse() {
+            return Ok(Seed(number));
+        }
+        if let Some(seed) = Seed::from_code(text) {
+            return Ok(seed);
+        }
+        // FNV-1a, mixed afterwards since FNV on its own is weak on short phrases
+        let hash = text
+            .to_lowercase()
+            .bytes()
+            .fold(0xCBF2_9CE4_8422_2325, |hash: u64, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100_0000_01B3)
+            });
+        Ok(Seed(
+            Seed(hash).derive(Stream::Phrase, &[]) >> (64 - SHORT_BITS),
+        ))
+    }
+
+    /// Only something shaped like a code counts, with a dash or exactly 8 digits long,
+    /// so single words like `dragon` are still phrases
+    fn from_code(code: &str) -> Option<Seed> {
+        if !code.contains('-') && code.chars().count() != 8 {
+            return None;
+        }
+        let mut value: u64 = 0;
+        let mut digits = 0;
+        for c in code.chars().filter(|c| *c != '-') {
+            let c = match c.to_ascii_uppercase() {
+                'O' => '0',
+                'I' | 'L' => '1',
+                c => c,
+            };
+            let digit = CODE_ALPHABET.iter().position(|a| *a as char == c)?;
+            value = value.checked_mul(32)?.checked_add(digit as u64)?;
+            digits += 1;
+        }
+        (digits > 0).then_some(Seed(value))
+    }
+}
+
+/// Groups of 4 split by dashes so there is always one, which keeps codes from being
+/// read back as plain numbers
+impl fmt::Display for Seed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut digits = Vec::new();
+        let mut value = self.0;
+        while value > 0 || digits.len() < 8 {
+            digits.push(CODE_ALPHABET[(value % 32) as usize] as char);
+            value /= 32;
+        }
+        for (i, digit) in digits.iter().rev().enumerate() {
+            if i > 0 && (digits.len() - i) % 4 == 0 {
+                write!(f, "-")?;
+            }
+            write!(f, "{digit}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Where each run's seed comes from
+#[derive(Resource, Default)]
+pub struct SeedSource {
+    /// given with `--seed`, otherwise every run gets a new seed
+    pub fixed: Option<Seed>,
+    /// play today's seed instead
+    pub daily: bool,
+    /// play the last run's seed once more
+    pub again: bool,
+}
+
+fn pick_seed(mut source: ResMut<SeedSource>, mut seed: ResMut<Seed>) {
+    if std::mem::take(&mut source.again) {
+        return;
+    }
+    *seed = if source.daily {
+        Seed::today()
+    } else {
+        source.fixed.unwrap_or_else(Seed::random)
+    };
+}
+
+/// Gameplay randomness, only use it from fixed timestep systems or replays will drift
+#[derive(Resource, Deref, DerefMut)]
+pub struct GameRng(pub StdRng);
+
+impl FromWorld for GameRng {
+    fn from_world(world: &mut World) -> Self {
+        let seed = *world.get_resource_or_insert_with(Seed::default);
+        GameRng::new(seed)
+    }
+}
+
+/// Every run with the same seed plays out the same
+fn reseed(seed: Res<Seed>, mut rng: ResMut<GameRng>) {
+    *rng = GameRng::new(*seed);
+}
+
+impl GameRng {
+    pub fn new(seed: Seed) -> Self {
+        GameRng(seed.rng(Stream::Gameplay, &[]))
+    }
+}
+
+#[test]
+fn codes_read_back_as_the_same_seed() {
+    for seed in [0, 1, 7, 1 << 39, (1 << 40) - 1, u64::MAX, Seed::random().0] {
+        let code = Seed(seed).to_string();
+        assert!(code.contains('-'), "{code}");
+        assert_eq!(Seed::parse(&code), Ok(Seed(seed)), "{code}");
+        assert_eq!(Seed::parse(&code.to_lowercase()), Ok(Seed(seed)));
+    }
+    assert_eq!(Seed(0).to_string(), "0000-0000");
+    assert_eq!(Seed::random().to_string().len(), 9);
+    assert_eq!(Seed::parse("42"), Ok(Seed(42)));
+    assert_eq!(Seed::parse("deep well"), Seed::parse("Deep Well"));
+    assert_eq!(Seed::parse("K3F9QX2A"), Seed::parse("K3F9-QX2A"));
+    assert!(Seed::parse("  ").is_err());
+}
+
+#[test]
+fn single_words_are_phrases() {
+    // every letter here is in the code alphabet
+    for word in ["abc", "hello", "dragon"] {
+        let seed = Seed::parse(word).unwrap();
+        assert_eq!(Seed::from_code(word), None);
+        assert_eq!(Seed::parse(&word.to_uppercase()), Ok(seed));
+    }
+    assert_ne!(Seed::parse("abc"), Seed::parse("abd"));
+}
+
+#[test]
+fn nearby_seeds_look_unrelated() {
+    // the old generator mixed with `seed + layer ^ seed`, which is just `layer` for some
+    // seeds and gave every seed the same first layer
+    assert_ne!(
+        Seed(1).derive(Stream::Chunks, &[0]),
+        Seed(2).derive(Stream::Chunks, &[0])
+    );
+    assert_ne!(
+        Seed(1).derive(Stream::Chunks, &[0]),
+        Seed(1).derive(Stream::Tiles, &[0])
+    );
+    let a = Seed(1).derive(Stream::Chunks, &[0]);
+    let b = Seed(1).derive(Stream::Chunks, &[1]);
+    let flipped = (a ^ b).count_ones();
+    assert!((16..48).contains(&flipped), "{flipped} bits differ");
+    assert_ne!(Seed::daily(20000), Seed::daily(20001));
+}
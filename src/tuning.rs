@@ -0,0 +1,165 @@
+//! How the player moves, read from `assets/tuning/player.tuning` and reloaded whenever
+//! the file changes so movement can be tuned without recompiling
+
+use avian2d::prelude::*;
+use bevy::{
+    asset::{io::file::FileAssetReader, AssetLoader, AsyncReadExt},
+    prelude::*,
+};
+
+use crate::{
+    player::{Player, PlayerStats},
+    save::write_atomic,
+};
+
+pub fn plugin(app: &mut App) {
+    app.init_asset::<PlayerTuning>()
+        .register_asset_loader(PlayerTuningLoader)
+        .init_resource::<Tuning>()
+        .insert_resource(Gravity(Vec2::NEG_Y * PlayerTuning::default().gravity))
+        .add_systems(Update, apply_tuning)
+        .add_systems(FixedPreUpdate, tune_new_players);
+}
+
+pub const TUNING_PATH: &str = "tuning/player.tuning";
+
+#[derive(serde::Deserialize, serde::Serialize, Asset, TypePath, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct PlayerTuning {
+    pub horizontal_speed: f32,
+    pub jump_speed: f32,
+    /// fastest a player can move up or down
+    pub speed_limit: f32,
+    pub climb_speed: f32,
+    /// how quickly a player stops when nothing is held, higher stops sooner
+    pub friction: f32,
+    pub gravity: f32,
+}
+
+impl Default for PlayerTuning {
+    fn default() -> Self {
+        PlayerTuning {
+            horizontal_speed: 100.,
+            jump_speed: 250.,
+            speed_limit: 250.,
+            climb_speed: 80.,
+            friction: 4.,
+            gravity: 500.,
+        }
+    }
+}
+
+impl PlayerTuning {
+    /// What a player starts a run with
+    pub fn stats(&self) -> PlayerStats {
+        PlayerStats {
+            horizontal_speed: self.horizontal_speed,
+            jump_speed: self.jump_speed,
+            speed_limit: self.speed_limit,
+            climb_speed: self.climb_speed,
+            magnet_radius: 0.,
+        }
+    }
+
+    /// Move `stats` by however much the tuning changed, so upgrades already picked
+    /// stay on top of it
+    fn retune(&self, old: &PlayerTuning, stats: &mut PlayerStats) {
+        stats.horizontal_speed += self.horizontal_speed - old.horizontal_speed;
+        stats.jump_speed += self.jump_speed - old.jump_speed;
+        stats.speed_limit += self.speed_limit - old.speed_limit;
+        stats.climb_speed += self.climb_speed - old.climb_speed;
+    }
+
+    /// Write back over the tuning file in the asset folder, found the same way the
+    /// `AssetServer` finds it so it works from any working directory
+    pub fn save(&self) -> Result<(), String> {
+        let path = FileAssetReader::new("assets").root_path().join(TUNING_PATH);
+        let data = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| e.to_string())?;
+        write_atomic(&path, data.as_bytes()).map_err(|e| format!("{path:?}: {e}"))
+    }
+}
+
+/// The tuning file and the values in use, which stay at the defaults until it loads
+#[derive(Resource)]
+pub struct Tuning {
+    pub handle: Handle<PlayerTuning>,
+    pub current: PlayerTuning,
+}
+
+impl FromWorld for Tuning {
+    fn from_world(world: &mut World) -> Self {
+        Tuning {
+            handle: world.resource::<AssetServer>().load(TUNING_PATH),
+            current: PlayerTuning::default(),
+        }
+    }
+}
+
+fn apply_tuning(
+    mut events: EventReader<AssetEvent<PlayerTuning>>,
+    mut tuning: ResMut<Tuning>,
+    assets: Res<Assets<PlayerTuning>>,
+    mut players: Query<&mut PlayerStats>,
+    mut gravity: ResMut<Gravity>,
+) {
+    let id = tuning.handle.id();
+    if !events
+        .read()
+        .any(|event| event.is_loaded_with_dependencies(id) || event.is_modified(id))
+    {
+        return;
+    }
+    let Some(new) = assets.get(id) else {
+        return;
+    };
+    if *new == tuning.current {
+        return;
+    }
+    for mut stats in &mut players {
+        new.retune(&tuning.current, &mut stats);
+    }
+    gravity.0 = Vec2::NEG_Y * new.gravity;
+    tuning.current = new.clone();
+}
+
+fn tune_new_players(mut players: Query<&mut PlayerStats, Added<Player>>, tuning: Res<Tuning>) {
+    for mut stats in &mut players {
+        *stats = tuning.current.stats();
+    }
+}
+
+struct PlayerTuningLoader;
+
+impl AssetLoader for PlayerTuningLoader {
+    type Asset = PlayerTuning;
+    type Settings = ();
+    type Error = &'static str;
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut bevy::asset::io::Reader,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut bevy::asset::LoadContext,
+    ) -> impl bevy::utils::ConditionalSendFuture<Output = Result<Self::Asset, Self::Error>> {
+        load_player_tuning(reader)
+    }
+    fn extensions(&self) -> &[&str] {
+        &["tuning"]
+    }
+}
+
+async fn load_player_tuning(
+    reader: &mut bevy::asset::io::Reader<'_>,
+) -> Result<PlayerTuning, &'static str> {
+    let mut data = String::new();
+    if reader.read_to_string(&mut data).await.is_err() {
+        return Err("Failed to read to string");
+    }
+    match ron::from_str(&data) {
+        Ok(data) => Ok(data),
+        Err(e) => {
+            error!("{e}");
+            Err("Ron Failed")
+        }
+    }
+}